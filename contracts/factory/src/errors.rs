@@ -0,0 +1,13 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    /// The factory has no admin or vault code yet.
+    NotInitialized = 1,
+    /// No vault is indexed at the given position.
+    VaultNotFound = 2,
+    /// An upgraded vault failed to migrate its storage.
+    MigrationFailed = 3,
+}
@@ -0,0 +1,183 @@
+use crate::errors::Error;
+use crate::index::{read_vault, read_vault_count, read_vaults_by, write_vault};
+use crate::storage_types::{
+    DataKey, VaultIndex, INSTANCE_BUMP_AMOUNT, INSTANCE_LIFETIME_THRESHOLD,
+};
+use crate::vault::{Price, Role, VaultClient, ALREADY_MIGRATED};
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, Symbol, Vec};
+
+#[contract]
+pub struct VaultFactory;
+
+#[contractimpl]
+impl VaultFactory {
+    // set up the factory with its admin and the vault code it deploys
+    pub fn __constructor(e: Env, admin: Address, vault_wasm_hash: BytesN<32>) {
+        e.storage().instance().set(&DataKey::Admin, &admin);
+        e.storage()
+            .instance()
+            .set(&DataKey::VaultWasmHash, &vault_wasm_hash);
+    }
+
+    // deploy a new vault at the address derived from the salt and register it in the index
+    // the factory administers the vault to be able to upgrade it, `admin` is granted the
+    // admin role as well
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_vault(
+        e: Env,
+        salt: BytesN<32>,
+        admin: Address,
+        seller: Address,
+        treasury: Address,
+        sell_token: Address,
+        buy_token: Address,
+        initial_price: Price,
+    ) -> Result<Address, Error> {
+        VaultFactory::get_admin(e.clone())?.require_auth();
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+        let factory = e.current_contract_address();
+        let wasm_hash = VaultFactory::get_vault_wasm_hash(e.clone())?;
+        let vault = e.deployer().with_current_contract(salt).deploy_v2(
+            wasm_hash,
+            (
+                factory.clone(),
+                seller.clone(),
+                treasury.clone(),
+                sell_token.clone(),
+                buy_token.clone(),
+                initial_price.clone(),
+            ),
+        );
+        VaultClient::new(&e, &vault).grant_role(&factory, &Role::Admin, &admin);
+
+        write_vault(&e, &vault, &seller, &sell_token, &buy_token);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_created"), vault.clone(), seller),
+            (treasury, sell_token, buy_token, initial_price),
+        );
+        Ok(vault)
+    }
+
+    // change the code deployed for new vaults
+    pub fn set_vault_wasm_hash(e: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        VaultFactory::get_admin(e.clone())?.require_auth();
+        e.storage()
+            .instance()
+            .set(&DataKey::VaultWasmHash, &new_wasm_hash);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_wasm_updated"),), new_wasm_hash);
+        Ok(())
+    }
+
+    // upgrade and migrate up to `limit` vaults starting at position `start`, and deploy new
+    // vaults with the same code. Vaults the factory no longer administers are skipped and
+    // returned.
+    pub fn upgrade_vaults(
+        e: Env,
+        new_wasm_hash: BytesN<32>,
+        start: u32,
+        limit: u32,
+    ) -> Result<Vec<Address>, Error> {
+        VaultFactory::get_admin(e.clone())?.require_auth();
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+        let factory = e.current_contract_address();
+        let end = read_vault_count(&e).min(start.saturating_add(limit));
+        let mut skipped = Vec::new(&e);
+        for index in start..end {
+            let vault = read_vault(&e, index)?;
+            let client = VaultClient::new(&e, &vault);
+            if !client.has_role(&Role::Admin, &factory) {
+                skipped.push_back(vault);
+                continue;
+            }
+            client.upgrade(&factory, &new_wasm_hash);
+            // code keeping the storage layout has nothing to migrate
+            match client.try_migrate(&factory) {
+                Ok(_) => {}
+                Err(Ok(error))
+                    if error == soroban_sdk::Error::from_contract_error(ALREADY_MIGRATED) => {}
+                Err(_) => return Err(Error::MigrationFailed),
+            }
+        }
+        e.storage()
+            .instance()
+            .set(&DataKey::VaultWasmHash, &new_wasm_hash);
+
+        e.events().publish(
+            (Symbol::new(&e, "vaults_upgraded"),),
+            (new_wasm_hash, start, end, skipped.clone()),
+        );
+        Ok(skipped)
+    }
+
+    // read functions
+
+    pub fn get_admin(e: Env) -> Result<Address, Error> {
+        e.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)
+    }
+
+    pub fn get_vault_wasm_hash(e: Env) -> Result<BytesN<32>, Error> {
+        e.storage()
+            .instance()
+            .get(&DataKey::VaultWasmHash)
+            .ok_or(Error::NotInitialized)
+    }
+
+    pub fn get_vault_address(e: Env, salt: BytesN<32>) -> Address {
+        e.deployer().with_current_contract(salt).deployed_address()
+    }
+
+    pub fn get_vault_count(e: Env) -> u32 {
+        read_vault_count(&e)
+    }
+
+    // the vault created at position `index`, counting from 0
+    pub fn get_vault(e: Env, index: u32) -> Result<Address, Error> {
+        read_vault(&e, index)
+    }
+
+    // vaults in creation order, starting at `start`
+    pub fn get_vaults(e: Env, start: u32, limit: u32) -> Result<Vec<Address>, Error> {
+        let end = read_vault_count(&e).min(start.saturating_add(limit));
+        let mut vaults = Vec::new(&e);
+        for index in start..end {
+            vaults.push_back(read_vault(&e, index)?);
+        }
+        Ok(vaults)
+    }
+
+    // vaults by the sell token they were created with, starting at `start`
+    pub fn get_vaults_by_sell_token(
+        e: Env,
+        sell_token: Address,
+        start: u32,
+        limit: u32,
+    ) -> Vec<Address> {
+        read_vaults_by(&e, VaultIndex::SellToken(sell_token), start, limit)
+    }
+
+    pub fn get_vaults_by_buy_token(
+        e: Env,
+        buy_token: Address,
+        start: u32,
+        limit: u32,
+    ) -> Vec<Address> {
+        read_vaults_by(&e, VaultIndex::BuyToken(buy_token), start, limit)
+    }
+
+    // vaults by the seller they were created with
+    pub fn get_vaults_by_seller(e: Env, seller: Address, start: u32, limit: u32) -> Vec<Address> {
+        read_vaults_by(&e, VaultIndex::Seller(seller), start, limit)
+    }
+}
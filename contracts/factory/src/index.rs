@@ -0,0 +1,92 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    DataKey, VaultIndex, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Address, Env, IntoVal, Val, Vec};
+
+pub fn read_vault_count(e: &Env) -> u32 {
+    e.storage()
+        .instance()
+        .get::<_, u32>(&DataKey::VaultCount)
+        .unwrap_or(0)
+}
+
+pub fn read_vault(e: &Env, index: u32) -> Result<Address, Error> {
+    let key = DataKey::Vault(index);
+    let vault = e
+        .storage()
+        .persistent()
+        .get::<_, Address>(&key)
+        .ok_or(Error::VaultNotFound)?;
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    Ok(vault)
+}
+
+pub fn read_index_count(e: &Env, index: &VaultIndex) -> u32 {
+    e.storage()
+        .persistent()
+        .get::<_, u32>(&DataKey::IndexCount(index.clone()))
+        .unwrap_or(0)
+}
+
+// up to `limit` vaults of the index starting at position `start`
+pub fn read_vaults_by(e: &Env, index: VaultIndex, start: u32, limit: u32) -> Vec<Address> {
+    let end = read_index_count(e, &index).min(start.saturating_add(limit));
+    let mut vaults = Vec::new(e);
+    for position in start..end {
+        let key = DataKey::IndexEntry(index.clone(), position);
+        if let Some(vault) = e.storage().persistent().get::<_, Address>(&key) {
+            e.storage().persistent().extend_ttl(
+                &key,
+                PERSISTENT_LIFETIME_THRESHOLD,
+                PERSISTENT_BUMP_AMOUNT,
+            );
+            vaults.push_back(vault);
+        }
+    }
+    vaults
+}
+
+fn write_index_entry<V: IntoVal<Env, Val>>(e: &Env, key: DataKey, value: &V) {
+    e.storage().persistent().set(&key, value);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+fn append_vault_by(e: &Env, index: VaultIndex, vault: &Address) {
+    let position = read_index_count(e, &index);
+    write_index_entry(e, DataKey::IndexEntry(index.clone(), position), vault);
+    write_index_entry(e, DataKey::IndexCount(index), &(position + 1));
+}
+
+// register a newly deployed vault under its position and its offer's tokens and seller
+pub fn write_vault(
+    e: &Env,
+    vault: &Address,
+    seller: &Address,
+    sell_token: &Address,
+    buy_token: &Address,
+) {
+    let index = read_vault_count(e);
+    let key = DataKey::Vault(index);
+    e.storage().persistent().set(&key, vault);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    e.storage()
+        .instance()
+        .set(&DataKey::VaultCount, &(index + 1));
+
+    append_vault_by(e, VaultIndex::SellToken(sell_token.clone()), vault);
+    append_vault_by(e, VaultIndex::BuyToken(buy_token.clone()), vault);
+    append_vault_by(e, VaultIndex::Seller(seller.clone()), vault);
+}
@@ -0,0 +1,8 @@
+#![no_std]
+
+mod errors;
+pub mod factory;
+mod index;
+mod storage_types;
+mod test;
+mod vault;
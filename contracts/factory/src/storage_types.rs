@@ -0,0 +1,28 @@
+use soroban_sdk::{contracttype, Address};
+
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub(crate) const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    VaultWasmHash,
+    VaultCount,
+    Vault(u32),
+    IndexCount(VaultIndex),
+    IndexEntry(VaultIndex, u32),
+}
+
+// secondary indexes of the vaults, each stored as one entry per position plus a count
+#[derive(Clone)]
+#[contracttype]
+pub enum VaultIndex {
+    SellToken(Address),
+    BuyToken(Address),
+    Seller(Address),
+}
@@ -0,0 +1,276 @@
+#![cfg(test)]
+extern crate std;
+
+use crate::errors::Error;
+use crate::factory::{VaultFactory, VaultFactoryClient};
+use crate::vault::Price;
+use soroban_sdk::{
+    testutils::{Address as _, AuthorizedFunction, Events},
+    token, vec, Address, BytesN, Env, IntoVal, Symbol,
+};
+
+mod vault {
+    soroban_sdk::contractimport!(file = "testdata/untangled_vault.wasm");
+}
+
+// smallest module the host accepts as contract code: only the environment
+// meta section declaring interface version 22
+const EMPTY_CONTRACT_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x00, 0x1e, 0x11, b'c', b'o', b'n', b't', b'r',
+    b'a', b'c', b't', b'e', b'n', b'v', b'm', b'e', b't', b'a', b'v', b'0', 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x16, 0x00, 0x00, 0x00, 0x00,
+];
+
+fn create_token_contract<'a>(e: &Env, admin: &Address) -> token::Client<'a> {
+    let sac = e.register_stellar_asset_contract_v2(admin.clone());
+    token::Client::new(e, &sac.address())
+}
+
+// price with 6 decimals
+fn price(mantissa: i128) -> Price {
+    Price {
+        mantissa,
+        decimals: 6,
+    }
+}
+
+fn create_factory_contract<'a>(e: &Env, admin: &Address) -> VaultFactoryClient<'a> {
+    let wasm_hash = e.deployer().upload_contract_wasm(vault::WASM);
+    VaultFactoryClient::new(e, &e.register(VaultFactory, (admin, wasm_hash)))
+}
+
+#[test]
+fn test_create_vault() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let factory_admin = Address::generate(&e);
+    let vault_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let other_seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+
+    let usdyc = create_token_contract(&e, &token_admin);
+    let usdc = create_token_contract(&e, &token_admin);
+    let eurc = create_token_contract(&e, &token_admin);
+
+    let factory = create_factory_contract(&e, &factory_admin);
+    assert_eq!(factory.get_admin(), factory_admin);
+    assert_eq!(factory.get_vault_count(), 0);
+
+    let salt = BytesN::from_array(&e, &[1; 32]);
+    let expected_address = factory.get_vault_address(&salt);
+    let first = factory.create_vault(
+        &salt,
+        &vault_admin,
+        &seller,
+        &treasury,
+        &usdyc.address,
+        &usdc.address,
+        &price(1000000),
+    );
+    assert_eq!(first, expected_address);
+    assert_eq!(e.auths()[0].0, factory_admin);
+    assert_eq!(
+        e.auths()[0].1.function,
+        AuthorizedFunction::Contract((
+            factory.address.clone(),
+            Symbol::new(&e, "create_vault"),
+            (
+                salt.clone(),
+                &vault_admin,
+                &seller,
+                &treasury,
+                &usdyc.address,
+                &usdc.address,
+                price(1000000)
+            )
+                .into_val(&e)
+        ))
+    );
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                factory.address.clone(),
+                (
+                    Symbol::new(&e, "vault_created"),
+                    first.clone(),
+                    seller.clone()
+                )
+                    .into_val(&e),
+                (
+                    treasury.clone(),
+                    usdyc.address.clone(),
+                    usdc.address.clone(),
+                    price(1000000)
+                )
+                    .into_val(&e)
+            )
+        ]
+    );
+
+    let vault_client = vault::Client::new(&e, &first);
+    let offer = vault_client.get_offer();
+    assert_eq!(offer.seller, seller);
+    assert_eq!(offer.treasury, treasury);
+    assert_eq!(offer.sell_token, usdyc.address);
+    assert_eq!(offer.buy_token, usdc.address);
+    assert_eq!(
+        offer.price,
+        vault::Price {
+            mantissa: 1000000,
+            decimals: 6
+        }
+    );
+    assert_eq!(offer.sell_decimals, 7);
+    assert_eq!(offer.buy_decimals, 7);
+    assert!(vault_client.has_role(&vault::Role::Admin, &factory.address));
+    assert!(vault_client.has_role(&vault::Role::Admin, &vault_admin));
+    assert!(vault_client.has_role(&vault::Role::PriceUpdater, &seller));
+    assert!(vault_client.has_role(&vault::Role::Settler, &treasury));
+
+    // the same salt can't be used twice
+    assert!(factory
+        .try_create_vault(
+            &salt,
+            &vault_admin,
+            &seller,
+            &treasury,
+            &usdyc.address,
+            &usdc.address,
+            &price(1000000),
+        )
+        .is_err());
+
+    let second = factory.create_vault(
+        &BytesN::from_array(&e, &[2; 32]),
+        &vault_admin,
+        &other_seller,
+        &treasury,
+        &usdyc.address,
+        &eurc.address,
+        &price(1100000),
+    );
+    assert_ne!(first, second);
+
+    assert_eq!(factory.get_vault_count(), 2);
+    assert_eq!(
+        factory.get_vaults(&0, &10),
+        vec![&e, first.clone(), second.clone()]
+    );
+    assert_eq!(factory.get_vaults(&1, &1), vec![&e, second.clone()]);
+    assert_eq!(factory.get_vaults(&2, &1), vec![&e]);
+    assert_eq!(factory.get_vault(&1), second);
+    assert_eq!(factory.try_get_vault(&2), Err(Ok(Error::VaultNotFound)));
+    assert_eq!(
+        factory.get_vaults_by_sell_token(&usdyc.address, &0, &10),
+        vec![&e, first.clone(), second.clone()]
+    );
+    assert_eq!(
+        factory.get_vaults_by_sell_token(&usdyc.address, &1, &1),
+        vec![&e, second.clone()]
+    );
+    assert_eq!(
+        factory.get_vaults_by_sell_token(&usdyc.address, &0, &1),
+        vec![&e, first.clone()]
+    );
+    assert_eq!(
+        factory.get_vaults_by_sell_token(&usdyc.address, &2, &1),
+        vec![&e]
+    );
+    assert_eq!(
+        factory.get_vaults_by_buy_token(&usdc.address, &0, &10),
+        vec![&e, first.clone()]
+    );
+    assert_eq!(
+        factory.get_vaults_by_buy_token(&eurc.address, &0, &10),
+        vec![&e, second.clone()]
+    );
+    assert_eq!(
+        factory.get_vaults_by_seller(&seller, &0, &10),
+        vec![&e, first]
+    );
+    assert_eq!(
+        factory.get_vaults_by_seller(&other_seller, &0, &10),
+        vec![&e, second]
+    );
+    assert_eq!(factory.get_vaults_by_seller(&treasury, &0, &10), vec![&e]);
+}
+
+#[test]
+fn test_upgrade_vaults() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let factory_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+
+    let usdyc = create_token_contract(&e, &token_admin);
+    let usdc = create_token_contract(&e, &token_admin);
+
+    let factory = create_factory_contract(&e, &factory_admin);
+    let vaults = [1_u8, 2, 3].map(|i| {
+        factory.create_vault(
+            &BytesN::from_array(&e, &[i; 32]),
+            &seller,
+            &seller,
+            &treasury,
+            &usdyc.address,
+            &usdc.address,
+            &price(1000000),
+        )
+    });
+
+    let wasm_hash = factory.get_vault_wasm_hash();
+    let empty_wasm_hash = e.deployer().upload_contract_wasm(EMPTY_CONTRACT_WASM);
+    factory.set_vault_wasm_hash(&empty_wasm_hash);
+    assert_eq!(e.auths()[0].0, factory_admin);
+    assert_eq!(factory.get_vault_wasm_hash(), empty_wasm_hash);
+
+    // code that cannot migrate the vaults is rolled back
+    assert_eq!(
+        factory.try_upgrade_vaults(&empty_wasm_hash, &0, &3),
+        Err(Ok(Error::MigrationFailed))
+    );
+    assert_eq!(
+        vault::Client::new(&e, &vaults[0]).get_offer().seller,
+        seller
+    );
+
+    // a vault whose admin removed the factory is skipped
+    vault::Client::new(&e, &vaults[1]).revoke_role(&seller, &vault::Role::Admin, &factory.address);
+    assert_eq!(
+        factory.upgrade_vaults(&wasm_hash, &0, &2),
+        vec![&e, vaults[1].clone()]
+    );
+    assert_eq!(e.auths()[0].0, factory_admin);
+    let upgraded = |vault: &Address| {
+        e.events().all().iter().any(|(contract, topics, _)| {
+            contract == *vault
+                && topics == (Symbol::new(&e, "vault_upgraded"), &factory.address).into_val(&e)
+        })
+    };
+    assert!(upgraded(&vaults[0]));
+    assert!(!upgraded(&vaults[1]));
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                factory.address.clone(),
+                (Symbol::new(&e, "vaults_upgraded"),).into_val(&e),
+                (wasm_hash.clone(), 0_u32, 2_u32, vec![&e, vaults[1].clone()]).into_val(&e)
+            )
+        ]
+    );
+
+    // the next page continues where the previous one stopped
+    assert_eq!(factory.upgrade_vaults(&wasm_hash, &2, &2), vec![&e]);
+    assert!(upgraded(&vaults[2]));
+    assert_eq!(factory.get_vault_wasm_hash(), wasm_hash);
+}
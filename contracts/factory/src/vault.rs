@@ -0,0 +1,34 @@
+use soroban_sdk::{contractclient, contracttype, Address, BytesN, Env};
+
+// mirror of the vault's `Role`, encoded the same way
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Role {
+    Admin,
+    PriceUpdater,
+    Settler,
+    Pauser,
+    Sweeper,
+    Compliance,
+}
+
+// mirror of the vault's `Price`, encoded the same way
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Price {
+    pub mantissa: i128,
+    pub decimals: u32,
+}
+
+// the vault's `Error::AlreadyMigrated`, raised by `migrate` when the schema did not change
+pub const ALREADY_MIGRATED: u32 = 16;
+
+// the vault entry points called by the factory
+#[allow(dead_code)]
+#[contractclient(name = "VaultClient")]
+pub trait Vault {
+    fn grant_role(e: Env, caller: Address, role: Role, account: Address);
+    fn has_role(e: Env, role: Role, account: Address) -> bool;
+    fn upgrade(e: Env, caller: Address, new_wasm_hash: BytesN<32>);
+    fn migrate(e: Env, caller: Address);
+}
@@ -0,0 +1,79 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD};
+use soroban_sdk::{Address, Env};
+
+pub fn read_balance(e: &Env, addr: Address) -> i128 {
+    let key = DataKey::Balance(addr);
+    if let Some(balance) = e.storage().persistent().get::<DataKey, i128>(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        balance
+    } else {
+        0
+    }
+}
+
+fn write_balance(e: &Env, addr: Address, amount: i128) {
+    let key = DataKey::Balance(addr);
+    e.storage().persistent().set(&key, &amount);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn receive_balance(e: &Env, addr: Address, amount: i128) -> Result<(), Error> {
+    let balance = read_balance(e, addr.clone());
+    write_balance(e, addr, balance.checked_add(amount).ok_or(Error::Overflow)?);
+    Ok(())
+}
+
+pub fn spend_balance(e: &Env, addr: Address, amount: i128) -> Result<(), Error> {
+    let balance = read_balance(e, addr.clone());
+    if balance < amount {
+        return Err(Error::InsufficientBalance);
+    }
+    write_balance(e, addr, balance.checked_sub(amount).ok_or(Error::Overflow)?);
+    Ok(())
+}
+
+pub fn read_total_supply(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get(&DataKey::TotalSupply)
+        .unwrap_or(0)
+}
+
+// shares are only created and destroyed by the vault, the supply moves with them
+pub fn mint_balance(e: &Env, addr: Address, amount: i128) -> Result<(), Error> {
+    receive_balance(e, addr, amount)?;
+    let total_supply = read_total_supply(e)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    e.storage()
+        .instance()
+        .set(&DataKey::TotalSupply, &total_supply);
+    Ok(())
+}
+
+pub fn burn_balance(e: &Env, addr: Address, amount: i128) -> Result<(), Error> {
+    spend_balance(e, addr, amount)?;
+    let total_supply = read_total_supply(e)
+        .checked_sub(amount)
+        .ok_or(Error::Overflow)?;
+    e.storage()
+        .instance()
+        .set(&DataKey::TotalSupply, &total_supply);
+    Ok(())
+}
+
+pub fn check_nonnegative_amount(amount: i128) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::NegativeAmount);
+    }
+    Ok(())
+}
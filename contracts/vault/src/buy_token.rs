@@ -0,0 +1,68 @@
+use crate::errors::Error;
+use crate::offer::load_offer;
+use crate::storage_types::{BuyToken, DataKey, Offer};
+use soroban_sdk::{Address, Env, Vec};
+
+pub fn read_buy_token(e: &Env, token: &Address) -> Option<BuyToken> {
+    e.storage()
+        .instance()
+        .get::<_, BuyToken>(&DataKey::BuyToken(token.clone()))
+}
+
+pub fn write_buy_token(e: &Env, token: &Address, buy_token: &BuyToken) {
+    let key = DataKey::BuyToken(token.clone());
+    if !e.storage().instance().has(&key) {
+        let mut tokens = read_buy_tokens(e);
+        tokens.push_back(token.clone());
+        e.storage().instance().set(&DataKey::BuyTokens, &tokens);
+    }
+    e.storage().instance().set(&key, buy_token);
+}
+
+// tokens accepted besides the offer's buy token, in the order they were added
+pub fn read_buy_tokens(e: &Env) -> Vec<Address> {
+    e.storage()
+        .instance()
+        .get::<_, Vec<Address>>(&DataKey::BuyTokens)
+        .unwrap_or(Vec::new(e))
+}
+
+// the offer's buy token followed by the other accepted tokens
+pub fn accepted_tokens(e: &Env, offer: &Offer) -> Vec<Address> {
+    let mut tokens = Vec::from_array(e, [offer.buy_token.clone()]);
+    tokens.append(&read_buy_tokens(e));
+    tokens
+}
+
+// the offer's buy token keeps the storage keys it had before other tokens were accepted,
+// the keys of added tokens include the token
+pub fn is_base_token(e: &Env, token: &Address) -> bool {
+    !e.storage()
+        .instance()
+        .has(&DataKey::BuyToken(token.clone()))
+}
+
+// the offer as seen by investors paying in `token`: the offer itself for its buy token,
+// otherwise the offer with the accepted token's price, decimals and treasury
+pub fn load_market(e: &Env, token: &Address) -> Result<Offer, Error> {
+    let offer = load_offer(e)?;
+    if *token == offer.buy_token {
+        return Ok(offer);
+    }
+    let buy_token = read_buy_token(e, token).ok_or(Error::TokenNotAccepted)?;
+    Ok(Offer {
+        treasury: buy_token.treasury,
+        buy_token: token.clone(),
+        price: buy_token.price,
+        buy_decimals: buy_token.decimals,
+        ..offer
+    })
+}
+
+// the market of a token open for deposits and redeem requests
+pub fn load_open_market(e: &Env, token: &Address) -> Result<Offer, Error> {
+    if read_buy_token(e, token).is_some_and(|buy_token| !buy_token.enabled) {
+        return Err(Error::TokenDisabled);
+    }
+    load_market(e, token)
+}
@@ -0,0 +1,109 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    ComplianceConfig, DataKey, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{contractclient, Address, Env};
+
+// external registry of verified investors, e.g. run by the KYC provider
+#[allow(dead_code)]
+#[contractclient(name = "ComplianceRegistryClient")]
+pub trait ComplianceRegistry {
+    fn is_verified(env: Env, account: Address) -> bool;
+}
+
+// no allowlist is enforced until one is configured
+pub fn read_compliance(e: &Env) -> ComplianceConfig {
+    e.storage()
+        .instance()
+        .get::<_, ComplianceConfig>(&DataKey::Compliance)
+        .unwrap_or(ComplianceConfig {
+            enforced: false,
+            registry: None,
+        })
+}
+
+pub fn write_compliance(e: &Env, config: &ComplianceConfig) {
+    e.storage().instance().set(&DataKey::Compliance, config);
+}
+
+// expiry timestamp of the account's allowlist entry, 0 if it does not expire
+pub fn read_allowed(e: &Env, account: &Address) -> Option<u64> {
+    let key = DataKey::Allowed(account.clone());
+    let expires_at = e.storage().persistent().get::<_, u64>(&key);
+    if expires_at.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    expires_at
+}
+
+pub fn write_allowed(e: &Env, account: &Address, expires_at: u64) {
+    let key = DataKey::Allowed(account.clone());
+    e.storage().persistent().set(&key, &expires_at);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn remove_allowed(e: &Env, account: &Address) {
+    e.storage()
+        .persistent()
+        .remove(&DataKey::Allowed(account.clone()));
+}
+
+pub fn is_denied(e: &Env, account: &Address) -> bool {
+    e.storage()
+        .persistent()
+        .has(&DataKey::Denied(account.clone()))
+}
+
+pub fn write_denied(e: &Env, account: &Address, denied: bool) {
+    let key = DataKey::Denied(account.clone());
+    if denied {
+        e.storage().persistent().set(&key, &true);
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    } else {
+        e.storage().persistent().remove(&key);
+    }
+}
+
+// sanctioned accounts cannot move anything in or out of the vault
+pub fn require_not_denied(e: &Env, account: &Address) -> Result<(), Error> {
+    if is_denied(e, account) {
+        return Err(Error::Denylisted);
+    }
+    Ok(())
+}
+
+// opening a position needs a valid allowlist entry or a verification by the registry
+pub fn require_compliant(e: &Env, account: &Address) -> Result<(), Error> {
+    require_not_denied(e, account)?;
+    let config = read_compliance(e);
+    if !config.enforced {
+        return Ok(());
+    }
+    let expires_at = read_allowed(e, account);
+    if let Some(expires_at) = expires_at {
+        if expires_at == 0 || e.ledger().timestamp() <= expires_at {
+            return Ok(());
+        }
+    }
+    if let Some(registry) = config.registry {
+        if ComplianceRegistryClient::new(e, &registry).is_verified(account) {
+            return Ok(());
+        }
+    }
+    if expires_at.is_some() {
+        return Err(Error::AllowlistExpired);
+    }
+    Err(Error::NotAllowlisted)
+}
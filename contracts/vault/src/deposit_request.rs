@@ -0,0 +1,67 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    DataKey, DepositRequest, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+
+use soroban_sdk::{Address, Env};
+
+pub fn read_deposit_request(e: &Env, sender: Address) -> DepositRequest {
+    let key = DataKey::DepositRequest(sender);
+    if let Some(request) = e.storage().persistent().get::<_, DepositRequest>(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        request
+    } else {
+        DepositRequest {
+            assets_amount: 0,
+            epoch_id: 0,
+        }
+    }
+}
+
+pub fn write_deposit_request(
+    e: &Env,
+    sender: Address,
+    amount: i128,
+    epoch_id: u32,
+) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::NegativeAmount);
+    }
+    let key = DataKey::DepositRequest(sender);
+    if amount == 0 {
+        e.storage().persistent().remove(&key);
+        return Ok(());
+    }
+    let request = DepositRequest {
+        assets_amount: amount,
+        epoch_id,
+    };
+    e.storage().persistent().set(&key, &request);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    Ok(())
+}
+
+pub fn delete_deposit_request(e: &Env, sender: Address) {
+    e.storage()
+        .persistent()
+        .remove(&DataKey::DepositRequest(sender));
+}
+
+pub fn extend_deposit_request(e: &Env, sender: Address) {
+    let key = DataKey::DepositRequest(sender);
+    if e.storage().persistent().has(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
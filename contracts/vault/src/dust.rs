@@ -0,0 +1,31 @@
+use crate::buy_token::is_base_token;
+use crate::errors::Error;
+use crate::storage_types::DataKey;
+use soroban_sdk::{Address, Env};
+
+fn dust_key(e: &Env, token: &Address) -> DataKey {
+    if is_base_token(e, token) {
+        DataKey::Dust
+    } else {
+        DataKey::TokenDust(token.clone())
+    }
+}
+
+// buy tokens left in the vault by rounding payouts down, owed to nobody
+pub fn read_dust(e: &Env, token: &Address) -> i128 {
+    e.storage().instance().get(&dust_key(e, token)).unwrap_or(0)
+}
+
+pub fn add_dust(e: &Env, token: &Address, amount: i128) -> Result<(), Error> {
+    let dust = read_dust(e, token)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    e.storage().instance().set(&dust_key(e, token), &dust);
+    Ok(())
+}
+
+pub fn take_dust(e: &Env, token: &Address) -> i128 {
+    let dust = read_dust(e, token);
+    e.storage().instance().remove(&dust_key(e, token));
+    dust
+}
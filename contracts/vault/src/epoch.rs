@@ -0,0 +1,286 @@
+use crate::buy_token::is_base_token;
+use crate::errors::Error;
+use crate::math::mul_div_floor;
+use crate::storage_types::{
+    DataKey, EpochDeposit, EpochFill, EpochInfo, EpochSchedule, EpochWindow, Price,
+    PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Address, Env};
+
+fn rate_key(e: &Env, token: &Address, epoch_id: u32) -> DataKey {
+    if is_base_token(e, token) {
+        DataKey::RedeemRate(epoch_id)
+    } else {
+        DataKey::TokenRedeemRate(token.clone(), epoch_id)
+    }
+}
+
+pub fn read_redeem_rate(e: &Env, token: &Address, epoch_id: u32) -> Option<Price> {
+    let key = rate_key(e, token, epoch_id);
+    let rate = e.storage().persistent().get::<_, Price>(&key);
+    if rate.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    rate
+}
+
+pub fn write_redeem_rate(e: &Env, token: &Address, epoch_id: u32, rate: &Price) {
+    let key = rate_key(e, token, epoch_id);
+    e.storage().persistent().set(&key, rate);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn extend_redeem_rate(e: &Env, token: &Address, epoch_id: u32) {
+    let key = rate_key(e, token, epoch_id);
+    if e.storage().persistent().has(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
+
+pub const FULL_FILL: u32 = 1000000;
+
+fn fill_key(e: &Env, token: &Address, epoch_id: u32) -> DataKey {
+    if is_base_token(e, token) {
+        DataKey::EpochFill(epoch_id)
+    } else {
+        DataKey::TokenEpochFill(token.clone(), epoch_id)
+    }
+}
+
+pub fn read_epoch_fill(e: &Env, token: &Address, epoch_id: u32) -> Option<EpochFill> {
+    let key = fill_key(e, token, epoch_id);
+    let fill = e.storage().persistent().get::<_, EpochFill>(&key);
+    if fill.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    fill
+}
+
+pub fn write_epoch_fill(e: &Env, token: &Address, epoch_id: u32, fill: &EpochFill) {
+    let key = fill_key(e, token, epoch_id);
+    e.storage().persistent().set(&key, fill);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn extend_epoch_fill(e: &Env, token: &Address, epoch_id: u32) {
+    let key = fill_key(e, token, epoch_id);
+    if e.storage().persistent().has(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
+
+// filled part of a request settled in the epoch. Claims share out what is left of the
+// epoch's filled shares, so the last claim takes the rounding dust and the total is exact.
+pub fn fill_shares(e: &Env, fill: &mut EpochFill, shares: i128) -> Result<i128, Error> {
+    let filled = if shares >= fill.shares_left {
+        fill.filled_left
+    } else {
+        mul_div_floor(e, shares, fill.filled_left, fill.shares_left)?
+    };
+    fill.shares_left = fill
+        .shares_left
+        .checked_sub(shares)
+        .ok_or(Error::Overflow)?;
+    fill.filled_left = fill
+        .filled_left
+        .checked_sub(filled)
+        .ok_or(Error::Overflow)?;
+    Ok(filled)
+}
+
+pub fn read_epoch_info(e: &Env, token: &Address, epoch_id: u32) -> Option<EpochInfo> {
+    let key = DataKey::EpochInfo(token.clone(), epoch_id);
+    let info = e.storage().persistent().get::<_, EpochInfo>(&key);
+    if info.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    info
+}
+
+pub fn write_epoch_info(e: &Env, token: &Address, info: &EpochInfo) {
+    let key = DataKey::EpochInfo(token.clone(), info.epoch_id);
+    e.storage().persistent().set(&key, info);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn extend_epoch_info(e: &Env, token: &Address, epoch_id: u32) {
+    let key = DataKey::EpochInfo(token.clone(), epoch_id);
+    if e.storage().persistent().has(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
+
+// count buy tokens paid out of the epoch's funding, epochs settled before the records
+// existed have none
+pub fn add_epoch_claim(e: &Env, token: &Address, epoch_id: u32, amount: i128) -> Result<(), Error> {
+    let Some(mut info) = read_epoch_info(e, token, epoch_id) else {
+        return Ok(());
+    };
+    info.claimed_asset = info
+        .claimed_asset
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    write_epoch_info(e, token, &info);
+    Ok(())
+}
+
+pub fn read_epoch_deposit(e: &Env, epoch_id: u32) -> Option<EpochDeposit> {
+    let key = DataKey::EpochDeposit(epoch_id);
+    let deposit = e.storage().persistent().get::<_, EpochDeposit>(&key);
+    if deposit.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    deposit
+}
+
+pub fn write_epoch_deposit(e: &Env, epoch_id: u32, deposit: &EpochDeposit) {
+    let key = DataKey::EpochDeposit(epoch_id);
+    e.storage().persistent().set(&key, deposit);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn extend_epoch_deposit(e: &Env, epoch_id: u32) {
+    let key = DataKey::EpochDeposit(epoch_id);
+    if e.storage().persistent().has(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
+
+// shares of a deposit settled in the epoch, shared out like the filled shares of redeem requests
+pub fn take_deposit_shares(e: &Env, epoch_id: u32, assets: i128) -> Result<i128, Error> {
+    let mut deposit = read_epoch_deposit(e, epoch_id).ok_or(Error::EpochNotSetled)?;
+    let shares = if assets >= deposit.assets_left {
+        deposit.shares_left
+    } else {
+        mul_div_floor(e, assets, deposit.shares_left, deposit.assets_left)?
+    };
+    deposit.assets_left = deposit
+        .assets_left
+        .checked_sub(assets)
+        .ok_or(Error::Overflow)?;
+    deposit.shares_left = deposit
+        .shares_left
+        .checked_sub(shares)
+        .ok_or(Error::Overflow)?;
+    write_epoch_deposit(e, epoch_id, &deposit);
+    Ok(shares)
+}
+
+pub fn read_schedule(e: &Env) -> Option<EpochSchedule> {
+    e.storage()
+        .instance()
+        .get::<_, EpochSchedule>(&DataKey::Schedule)
+}
+
+pub fn write_schedule(e: &Env, schedule: &EpochSchedule) {
+    e.storage().instance().set(&DataKey::Schedule, schedule);
+}
+
+pub fn write_epoch_start(e: &Env, epoch_id: u32, start: u64) {
+    let key = DataKey::EpochStart(epoch_id);
+    e.storage().persistent().set(&key, &start);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+// timestamps of the epoch, only known for epochs started while a schedule was set
+pub fn read_epoch_window(e: &Env, epoch_id: u32) -> Option<EpochWindow> {
+    let schedule = read_schedule(e)?;
+    let key = DataKey::EpochStart(epoch_id);
+    let start = e.storage().persistent().get::<_, u64>(&key)?;
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    Some(EpochWindow {
+        start,
+        cutoff: start.saturating_add(schedule.cutoff_offset),
+        settle_after: start.saturating_add(schedule.settlement_offset),
+    })
+}
+
+// epoch a redeem request made now belongs to, requests after the cutoff roll into the next one
+pub fn request_epoch_id(e: &Env, epoch_id: u32) -> Result<u32, Error> {
+    match read_epoch_window(e, epoch_id) {
+        Some(window) if e.ledger().timestamp() > window.cutoff => {
+            epoch_id.checked_add(1).ok_or(Error::Overflow)
+        }
+        _ => Ok(epoch_id),
+    }
+}
+
+// settling is allowed once the earliest settlement time of the epoch is reached
+pub fn can_settle(e: &Env, epoch_id: u32) -> bool {
+    match read_epoch_window(e, epoch_id) {
+        Some(window) => e.ledger().timestamp() >= window.settle_after,
+        None => true,
+    }
+}
+
+// the next epoch starts one period after the settled one, or now if settlement was later
+pub fn start_next_epoch(e: &Env, epoch_id: u32) -> Result<(), Error> {
+    let Some(schedule) = read_schedule(e) else {
+        return Ok(());
+    };
+    let Some(window) = read_epoch_window(e, epoch_id) else {
+        return Ok(());
+    };
+    let start = window
+        .start
+        .saturating_add(schedule.period)
+        .max(e.ledger().timestamp());
+    write_epoch_start(e, epoch_id.checked_add(1).ok_or(Error::Overflow)?, start);
+    Ok(())
+}
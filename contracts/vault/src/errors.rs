@@ -12,10 +12,10 @@ pub enum Error {
     PriceTooLow = 3,
     /// The price is zero.
     ZeroPrice = 4,
-    /// The token transfer failed.
-    TokenTransferFailed = 5,
-    /// The token amount is zero.
-    ZeroTokenAmount = 6,
+    /// The buy token is disabled
+    TokenDisabled = 5,
+    /// The token is already accepted as a buy token
+    TokenAlreadyAccepted = 6,
     /// No redeem request found.
     NoRedeemRequest = 7,
     /// Epoch haven't setled yet.
@@ -24,4 +24,82 @@ pub enum Error {
     NegativeRedeemAmount = 9,
     /// Invalid epoch id
     InvalidEpochId = 10,
+    /// The caller does not hold the required role
+    Unauthorized = 11,
+    /// The account already holds the role
+    RoleAlreadyGranted = 12,
+    /// The account does not hold the role
+    RoleNotGranted = 13,
+    /// No seller or treasury transfer is pending
+    NoPendingTransfer = 14,
+    /// The pending transfer was not accepted in time
+    TransferExpired = 15,
+    /// The storage is already on the current schema version
+    AlreadyMigrated = 16,
+    /// The operation is paused
+    Paused = 17,
+    /// The oracle quote is missing or older than the configured max age
+    StalePrice = 18,
+    /// The oracle quote is too far from the stored price
+    PriceOutOfBounds = 19,
+    /// The fee is above the maximum
+    FeeTooHigh = 20,
+    /// The schedule offsets are inconsistent
+    InvalidSchedule = 21,
+    /// The earliest settlement time of the epoch is not reached
+    SettlementTooEarly = 22,
+    /// The pending redeem request belongs to another epoch
+    RequestInOtherEpoch = 23,
+    /// No deposit request found
+    NoDepositRequest = 24,
+    // 25 is folded into `NegativeAmount`, a contract error spec holds 50 variants at most
+    /// The vault does not issue its own shares
+    NotShareToken = 26,
+    /// Shares were already issued
+    SharesAlreadyIssued = 27,
+    /// Negative token amount
+    NegativeAmount = 28,
+    /// The share balance is too low
+    InsufficientBalance = 29,
+    /// The share allowance is too low
+    InsufficientAllowance = 30,
+    /// The allowance expires before the current ledger
+    InvalidExpiration = 31,
+    /// The account is not on the allowlist
+    NotAllowlisted = 32,
+    /// The account's allowlist entry has expired
+    AllowlistExpired = 33,
+    /// The account is on the denylist
+    Denylisted = 34,
+    /// The limits are negative or their minimum is above their maximum
+    InvalidLimits = 35,
+    /// The deposit is below the minimum amount
+    DepositTooSmall = 36,
+    /// The deposit is above the maximum amount
+    DepositTooLarge = 37,
+    /// The redeem request is below the minimum amount
+    RedeemTooSmall = 38,
+    /// The redeem request is above the maximum amount
+    RedeemTooLarge = 39,
+    /// The user's deposits would exceed the per-user cap
+    UserDepositCapExceeded = 40,
+    /// The user's redeem requests would exceed the per-user cap
+    UserRedeemCapExceeded = 41,
+    /// The epoch's redeem requests would exceed the per-epoch cap
+    EpochRedeemCapExceeded = 42,
+    /// The vault's assets would exceed the TVL cap
+    TvlCapExceeded = 43,
+    /// The price is negative or has too many decimals
+    InvalidPrice = 44,
+    /// No rounding dust to sweep
+    NoDust = 45,
+    /// An amount does not fit in 128 bits
+    Overflow = 46,
+    /// The vault or treasury holds too few tokens to pay out
+    InsufficientLiquidity = 47,
+    // 48 is folded into `OfferNotCreated`
+    /// The token is not accepted as a buy token
+    TokenNotAccepted = 49,
+    /// The amount exceeds what the vault holds beyond its reserves
+    InsufficientSurplus = 52,
 }
@@ -0,0 +1,30 @@
+use crate::errors::Error;
+use crate::math::mul_div_ceil;
+use crate::offer::load_offer;
+use crate::storage_types::{DataKey, Fees};
+use soroban_sdk::Env;
+
+// highest entry or exit fee that can be configured, 10%
+pub const MAX_FEE_BPS: u32 = 1000;
+
+// vaults start without fees, collected fees go to the treasury until a recipient is set
+pub fn read_fees(e: &Env) -> Result<Fees, Error> {
+    if let Some(fees) = e.storage().instance().get::<_, Fees>(&DataKey::Fees) {
+        Ok(fees)
+    } else {
+        Ok(Fees {
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            recipient: load_offer(e)?.treasury,
+        })
+    }
+}
+
+pub fn write_fees(e: &Env, fees: &Fees) {
+    e.storage().instance().set(&DataKey::Fees, fees);
+}
+
+// fees are rounded up, in the vault's favor
+pub fn fee_amount(e: &Env, amount: i128, fee_bps: u32) -> Result<i128, Error> {
+    mul_div_ceil(e, amount, fee_bps as i128, 10000)
+}
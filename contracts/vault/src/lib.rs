@@ -1,8 +1,27 @@
 #![no_std]
 
+mod allowance;
+mod balance;
+mod buy_token;
+mod compliance;
+mod deposit_request;
+mod dust;
+mod epoch;
 mod errors;
+mod fees;
+mod limits;
+mod math;
 mod offer;
+mod operator;
+mod oracle;
+mod ownership;
+mod pause;
+mod price;
 mod redeem_request;
+mod reserve;
+mod roles;
+mod share;
 mod storage_types;
 mod test;
-mod vault;
+mod upgrade;
+pub mod vault;
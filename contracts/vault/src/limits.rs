@@ -0,0 +1,182 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    DataKey, Limits, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Address, Env};
+
+// vaults start without limits
+pub fn read_limits(e: &Env) -> Limits {
+    e.storage()
+        .instance()
+        .get::<_, Limits>(&DataKey::Limits)
+        .unwrap_or(Limits {
+            min_deposit: 0,
+            max_deposit: 0,
+            min_redeem: 0,
+            max_redeem: 0,
+            user_deposit_cap: 0,
+            user_redeem_cap: 0,
+            epoch_redeem_cap: 0,
+            tvl_cap: 0,
+        })
+}
+
+pub fn write_limits(e: &Env, limits: &Limits) -> Result<(), Error> {
+    if limits.min_deposit < 0
+        || limits.min_redeem < 0
+        || limits.user_deposit_cap < 0
+        || limits.user_redeem_cap < 0
+        || limits.epoch_redeem_cap < 0
+        || limits.tvl_cap < 0
+        || !within_cap(limits.min_deposit, limits.max_deposit)
+        || !within_cap(limits.min_redeem, limits.max_redeem)
+    {
+        return Err(Error::InvalidLimits);
+    }
+    e.storage().instance().set(&DataKey::Limits, limits);
+    Ok(())
+}
+
+// a cap of 0 means no limit
+fn within_cap(amount: i128, cap: i128) -> bool {
+    cap == 0 || amount <= cap
+}
+
+fn read_counter(e: &Env, key: &DataKey) -> i128 {
+    if let Some(amount) = e.storage().persistent().get::<_, i128>(key) {
+        e.storage().persistent().extend_ttl(
+            key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        amount
+    } else {
+        0
+    }
+}
+
+fn write_counter(e: &Env, key: &DataKey, amount: i128) {
+    if amount <= 0 {
+        e.storage().persistent().remove(key);
+        return;
+    }
+    e.storage().persistent().set(key, &amount);
+    e.storage()
+        .persistent()
+        .extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+}
+
+// buy tokens deposited by the user, net of cancelled deposit requests
+pub fn read_user_deposited(e: &Env, user: &Address) -> i128 {
+    read_counter(e, &DataKey::UserDeposited(user.clone()))
+}
+
+// shares requested to redeem by the user, net of cancelled requests
+pub fn read_user_redeemed(e: &Env, user: &Address) -> i128 {
+    read_counter(e, &DataKey::UserRedeemed(user.clone()))
+}
+
+// buy tokens deposited into the vault minus those paid out to redemptions
+pub fn read_total_assets(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get::<_, i128>(&DataKey::TotalAssets)
+        .unwrap_or(0)
+}
+
+fn write_total_assets(e: &Env, amount: i128) {
+    e.storage()
+        .instance()
+        .set(&DataKey::TotalAssets, &amount.max(0));
+}
+
+// check a deposit against the limits that do not depend on the user
+pub fn check_deposit(e: &Env, amount: i128) -> Result<(), Error> {
+    let limits = read_limits(e);
+    if amount < limits.min_deposit {
+        return Err(Error::DepositTooSmall);
+    }
+    if !within_cap(amount, limits.max_deposit) {
+        return Err(Error::DepositTooLarge);
+    }
+    let total_assets = read_total_assets(e)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    if !within_cap(total_assets, limits.tvl_cap) {
+        return Err(Error::TvlCapExceeded);
+    }
+    Ok(())
+}
+
+// check a deposit of the user against the limits and count it towards the caps
+pub fn add_deposit(e: &Env, user: &Address, amount: i128) -> Result<(), Error> {
+    check_deposit(e, amount)?;
+    let user_deposited = read_user_deposited(e, user)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    if !within_cap(user_deposited, read_limits(e).user_deposit_cap) {
+        return Err(Error::UserDepositCapExceeded);
+    }
+    write_counter(e, &DataKey::UserDeposited(user.clone()), user_deposited);
+    let total_assets = read_total_assets(e)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    write_total_assets(e, total_assets);
+    Ok(())
+}
+
+// give back the room taken by a cancelled deposit request
+pub fn remove_deposit(e: &Env, user: &Address, amount: i128) -> Result<(), Error> {
+    let user_deposited = read_user_deposited(e, user)
+        .checked_sub(amount)
+        .ok_or(Error::Overflow)?;
+    write_counter(e, &DataKey::UserDeposited(user.clone()), user_deposited);
+    remove_assets(e, amount)
+}
+
+// check a redeem request against the limits that do not depend on the user, `epoch_total`
+// being the requested shares of its epoch including this request
+pub fn check_redeem(e: &Env, amount: i128, epoch_total: i128) -> Result<(), Error> {
+    let limits = read_limits(e);
+    if amount < limits.min_redeem {
+        return Err(Error::RedeemTooSmall);
+    }
+    if !within_cap(amount, limits.max_redeem) {
+        return Err(Error::RedeemTooLarge);
+    }
+    if !within_cap(epoch_total, limits.epoch_redeem_cap) {
+        return Err(Error::EpochRedeemCapExceeded);
+    }
+    Ok(())
+}
+
+// check a redeem request of the user against the limits and count it towards the user cap
+pub fn add_redeem(e: &Env, user: &Address, amount: i128, epoch_total: i128) -> Result<(), Error> {
+    check_redeem(e, amount, epoch_total)?;
+    let user_redeemed = read_user_redeemed(e, user)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    if !within_cap(user_redeemed, read_limits(e).user_redeem_cap) {
+        return Err(Error::UserRedeemCapExceeded);
+    }
+    write_counter(e, &DataKey::UserRedeemed(user.clone()), user_redeemed);
+    Ok(())
+}
+
+// give back the room taken by a cancelled redeem request
+pub fn remove_redeem(e: &Env, user: &Address, amount: i128) -> Result<(), Error> {
+    let user_redeemed = read_user_redeemed(e, user)
+        .checked_sub(amount)
+        .ok_or(Error::Overflow)?;
+    write_counter(e, &DataKey::UserRedeemed(user.clone()), user_redeemed);
+    Ok(())
+}
+
+// buy tokens paid out at settlement leave the vault
+pub fn remove_assets(e: &Env, amount: i128) -> Result<(), Error> {
+    let total_assets = read_total_assets(e)
+        .checked_sub(amount)
+        .ok_or(Error::Overflow)?;
+    write_total_assets(e, total_assets);
+    Ok(())
+}
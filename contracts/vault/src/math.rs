@@ -0,0 +1,59 @@
+use crate::errors::Error;
+use soroban_sdk::{Env, I256};
+
+// direction a division is rounded in. Amounts paid out by the vault are rounded down and
+// amounts it charges are rounded up, so the dust always stays with the vault.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+}
+
+// a * b / c rounded down
+pub fn mul_div_floor(e: &Env, a: i128, b: i128, c: i128) -> Result<i128, Error> {
+    mul_div_pow10(e, a, b, c, 0, Rounding::Floor)
+}
+
+// a * b / c rounded up
+pub fn mul_div_ceil(e: &Env, a: i128, b: i128, c: i128) -> Result<i128, Error> {
+    mul_div_pow10(e, a, b, c, 0, Rounding::Ceil)
+}
+
+// a * b * 10^exponent / c with a 256 bit intermediate, failing with `Overflow` if the result
+// does not fit in an i128
+pub fn mul_div_pow10(
+    e: &Env,
+    a: i128,
+    b: i128,
+    c: i128,
+    exponent: i64,
+    rounding: Rounding,
+) -> Result<i128, Error> {
+    let numerator = I256::from_i128(e, a)
+        .mul(&I256::from_i128(e, b))
+        .mul(&pow10(e, exponent.max(0) as u32));
+    let denominator = I256::from_i128(e, c).mul(&pow10(e, (-exponent).max(0) as u32));
+    div_round(e, &numerator, &denominator, rounding)
+        .to_i128()
+        .ok_or(Error::Overflow)
+}
+
+pub fn pow10(e: &Env, exponent: u32) -> I256 {
+    I256::from_i32(e, 10).pow(exponent)
+}
+
+// the host division truncates towards zero, step to the neighbour when it went the wrong way
+fn div_round(e: &Env, numerator: &I256, denominator: &I256, rounding: Rounding) -> I256 {
+    let zero = I256::from_i32(e, 0);
+    let quotient = numerator.div(denominator);
+    let remainder = numerator.sub(&quotient.mul(denominator));
+    if remainder == zero {
+        return quotient;
+    }
+    let positive = (remainder > zero) == (*denominator > zero);
+    match rounding {
+        Rounding::Floor if !positive => quotient.sub(&I256::from_i32(e, 1)),
+        Rounding::Ceil if positive => quotient.add(&I256::from_i32(e, 1)),
+        _ => quotient,
+    }
+}
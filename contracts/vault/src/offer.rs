@@ -1,20 +1,27 @@
+use crate::errors::Error;
+use crate::share::read_share_decimals;
 use crate::storage_types::{DataKey, Offer};
-use soroban_sdk::{Address, Env};
+use soroban_sdk::{token, Address, Env};
 
-pub fn load_offer(e: &Env) -> Offer {
-    if let Some(offer) = e.storage().instance().get::<_, Offer>(&DataKey::Offer) {
-        offer
-    } else {
-        Offer {
-            seller: Address::from_str(e, "0"),
-            treasury: Address::from_str(e, "0"),
-            sell_token: Address::from_str(e, "0"),
-            buy_token: Address::from_str(e, "0"),
-            price: 0,
-        }
-    }
+pub fn load_offer(e: &Env) -> Result<Offer, Error> {
+    e.storage()
+        .instance()
+        .get::<_, Offer>(&DataKey::Offer)
+        .ok_or(Error::OfferNotCreated)
 }
 
 pub fn write_offer(e: &Env, offer: &Offer) {
     e.storage().instance().set(&DataKey::Offer, offer);
 }
+
+// decimals of the sell and buy tokens. The vault's own shares have the buy token's decimals
+// until their metadata is set.
+pub fn read_token_decimals(e: &Env, sell_token: &Address, buy_token: &Address) -> (u32, u32) {
+    let buy_decimals = token::Client::new(e, buy_token).decimals();
+    let sell_decimals = if *sell_token == e.current_contract_address() {
+        read_share_decimals(e).unwrap_or(buy_decimals)
+    } else {
+        token::Client::new(e, sell_token).decimals()
+    };
+    (sell_decimals, buy_decimals)
+}
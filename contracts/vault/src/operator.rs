@@ -0,0 +1,98 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    DataKey, OperatorScope, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Address, Env};
+
+// expiry timestamp of the operator's approval for the scope, 0 if it does not expire
+pub fn read_operator(
+    e: &Env,
+    owner: &Address,
+    operator: &Address,
+    scope: OperatorScope,
+) -> Option<u64> {
+    let key = DataKey::Operator(owner.clone(), operator.clone(), scope);
+    let expires_at = e.storage().persistent().get::<_, u64>(&key);
+    if expires_at.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    expires_at
+}
+
+pub fn write_operator(
+    e: &Env,
+    owner: &Address,
+    operator: &Address,
+    scope: OperatorScope,
+    expires_at: u64,
+) -> Result<(), Error> {
+    if expires_at != 0 && expires_at <= e.ledger().timestamp() {
+        return Err(Error::InvalidExpiration);
+    }
+    let key = DataKey::Operator(owner.clone(), operator.clone(), scope);
+    e.storage().persistent().set(&key, &expires_at);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    Ok(())
+}
+
+pub fn remove_operator(e: &Env, owner: &Address, operator: &Address, scope: OperatorScope) {
+    e.storage()
+        .persistent()
+        .remove(&DataKey::Operator(owner.clone(), operator.clone(), scope));
+}
+
+// the operator must authorize the call and hold an approval of the owner for the scope that
+// has not expired
+pub fn require_operator(
+    e: &Env,
+    owner: &Address,
+    operator: &Address,
+    scope: OperatorScope,
+) -> Result<(), Error> {
+    operator.require_auth();
+    match read_operator(e, owner, operator, scope) {
+        Some(expires_at) if expires_at == 0 || e.ledger().timestamp() <= expires_at => Ok(()),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+// an owner always receives its own funds, other receivers must be approved
+pub fn is_receiver(e: &Env, owner: &Address, receiver: &Address) -> bool {
+    if owner == receiver {
+        return true;
+    }
+    let key = DataKey::Receiver(owner.clone(), receiver.clone());
+    let approved = e.storage().persistent().has(&key);
+    if approved {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+    approved
+}
+
+pub fn write_receiver(e: &Env, owner: &Address, receiver: &Address) {
+    let key = DataKey::Receiver(owner.clone(), receiver.clone());
+    e.storage().persistent().set(&key, &true);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn remove_receiver(e: &Env, owner: &Address, receiver: &Address) {
+    e.storage()
+        .persistent()
+        .remove(&DataKey::Receiver(owner.clone(), receiver.clone()));
+}
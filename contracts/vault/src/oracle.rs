@@ -0,0 +1,109 @@
+use crate::buy_token::is_base_token;
+use crate::errors::Error;
+use crate::price::{deviation_bps, MAX_PRICE_DECIMALS};
+use crate::storage_types::{DataKey, Offer, OracleConfig, Price};
+use soroban_sdk::{contractclient, contracttype, Address, Env, Symbol};
+
+// asset identifier of the SEP-40 price feed interface
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+// price record of the SEP-40 price feed interface
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+// the SEP-40 price feed entry points read by the vault
+#[allow(dead_code)]
+#[contractclient(name = "PriceFeedClient")]
+pub trait PriceFeed {
+    fn decimals(e: Env) -> u32;
+    fn lastprice(e: Env, asset: Asset) -> Option<PriceData>;
+}
+
+pub fn read_oracle(e: &Env) -> Option<OracleConfig> {
+    e.storage()
+        .instance()
+        .get::<_, OracleConfig>(&DataKey::Oracle)
+}
+
+pub fn write_oracle(e: &Env, config: &OracleConfig) {
+    e.storage().instance().set(&DataKey::Oracle, config);
+}
+
+pub fn remove_oracle(e: &Env) {
+    e.storage().instance().remove(&DataKey::Oracle);
+}
+
+pub fn read_token_oracle(e: &Env, token: &Address) -> Option<OracleConfig> {
+    e.storage()
+        .instance()
+        .get::<_, OracleConfig>(&DataKey::TokenOracle(token.clone()))
+}
+
+pub fn write_token_oracle(e: &Env, token: &Address, config: &OracleConfig) {
+    e.storage()
+        .instance()
+        .set(&DataKey::TokenOracle(token.clone()), config);
+}
+
+pub fn remove_token_oracle(e: &Env, token: &Address) {
+    e.storage()
+        .instance()
+        .remove(&DataKey::TokenOracle(token.clone()));
+}
+
+// price feed of the market's buy token, the offer's buy token using the vault's oracle
+fn market_oracle(e: &Env, market: &Offer) -> Option<OracleConfig> {
+    if is_base_token(e, &market.buy_token) {
+        read_oracle(e)
+    } else {
+        read_token_oracle(e, &market.buy_token)
+    }
+}
+
+// price to trade at: the oracle quote when one is configured, otherwise the stored price.
+// A missing, stale or out-of-band quote falls back to the stored price if the config
+// allows it and fails otherwise.
+pub fn current_price(e: &Env, offer: &Offer) -> Result<Price, Error> {
+    let Some(config) = market_oracle(e, offer) else {
+        return Ok(offer.price.clone());
+    };
+    match oracle_price(e, offer, &config) {
+        Ok(price) => Ok(price),
+        Err(_) if config.allow_fallback => Ok(offer.price.clone()),
+        Err(error) => Err(error),
+    }
+}
+
+fn oracle_price(e: &Env, offer: &Offer, config: &OracleConfig) -> Result<Price, Error> {
+    let feed = PriceFeedClient::new(e, &config.oracle);
+    let quote = feed.lastprice(&config.asset).ok_or(Error::StalePrice)?;
+    let age = e.ledger().timestamp().saturating_sub(quote.timestamp);
+    if age > config.max_age {
+        return Err(Error::StalePrice);
+    }
+
+    // the quote keeps the decimals of the price feed
+    let decimals = feed.decimals();
+    if quote.price <= 0 || decimals > MAX_PRICE_DECIMALS {
+        return Err(Error::PriceOutOfBounds);
+    }
+    let price = Price {
+        mantissa: quote.price,
+        decimals,
+    };
+
+    let deviation = deviation_bps(e, &price, &offer.price)?;
+    if deviation > config.max_deviation_bps as i128 {
+        return Err(Error::PriceOutOfBounds);
+    }
+    Ok(price)
+}
@@ -0,0 +1,43 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, PendingTransfer};
+use soroban_sdk::{Address, Env};
+
+// time window, in seconds, during which a proposed address can accept
+pub const PROPOSAL_EXPIRY: u64 = 7 * 24 * 60 * 60;
+
+pub fn read_pending(e: &Env, key: &DataKey) -> Option<PendingTransfer> {
+    e.storage().instance().get::<_, PendingTransfer>(key)
+}
+
+pub fn write_pending(
+    e: &Env,
+    key: &DataKey,
+    new_address: Address,
+) -> Result<PendingTransfer, Error> {
+    let pending = PendingTransfer {
+        new_address,
+        expires_at: e
+            .ledger()
+            .timestamp()
+            .checked_add(PROPOSAL_EXPIRY)
+            .ok_or(Error::Overflow)?,
+    };
+    e.storage().instance().set(key, &pending);
+    Ok(pending)
+}
+
+pub fn remove_pending(e: &Env, key: &DataKey) -> Result<PendingTransfer, Error> {
+    let pending = read_pending(e, key).ok_or(Error::NoPendingTransfer)?;
+    e.storage().instance().remove(key);
+    Ok(pending)
+}
+
+// remove the pending transfer and require the proposed address to accept it in time
+pub fn accept_pending(e: &Env, key: &DataKey) -> Result<Address, Error> {
+    let pending = remove_pending(e, key)?;
+    if e.ledger().timestamp() > pending.expires_at {
+        return Err(Error::TransferExpired);
+    }
+    pending.new_address.require_auth();
+    Ok(pending.new_address)
+}
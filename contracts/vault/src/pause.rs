@@ -0,0 +1,23 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, Operation};
+use soroban_sdk::Env;
+
+pub fn is_paused(e: &Env, operation: Operation) -> bool {
+    e.storage().instance().has(&DataKey::Paused(operation))
+}
+
+pub fn write_paused(e: &Env, operation: Operation, paused: bool) {
+    let key = DataKey::Paused(operation);
+    if paused {
+        e.storage().instance().set(&key, &true);
+    } else {
+        e.storage().instance().remove(&key);
+    }
+}
+
+pub fn require_not_paused(e: &Env, operation: Operation) -> Result<(), Error> {
+    if is_paused(e, operation) {
+        return Err(Error::Paused);
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::errors::Error;
+use crate::math::{mul_div_pow10, pow10, Rounding};
+use crate::storage_types::{Offer, Price};
+use soroban_sdk::{Env, I256};
+
+// most decimals a price can be given with
+pub const MAX_PRICE_DECIMALS: u32 = 18;
+
+// decimals of the u32 prices stored up to schema version 4
+pub const LEGACY_PRICE_DECIMALS: u32 = 6;
+
+pub fn legacy_price(price: u32) -> Price {
+    Price {
+        mantissa: price as i128,
+        decimals: LEGACY_PRICE_DECIMALS,
+    }
+}
+
+pub fn check_price(price: &Price) -> Result<(), Error> {
+    if price.mantissa == 0 {
+        return Err(Error::ZeroPrice);
+    }
+    if price.mantissa < 0 || price.decimals > MAX_PRICE_DECIMALS {
+        return Err(Error::InvalidPrice);
+    }
+    Ok(())
+}
+
+// buy token units worth `shares` sell token units at `price`, rounded down as the vault
+// pays them out
+pub fn shares_to_assets(
+    e: &Env,
+    offer: &Offer,
+    price: &Price,
+    shares: i128,
+) -> Result<i128, Error> {
+    let exponent = offer.buy_decimals as i64 - price.decimals as i64 - offer.sell_decimals as i64;
+    mul_div_pow10(e, shares, price.mantissa, 1, exponent, Rounding::Floor)
+}
+
+// sell token units bought by `assets` buy token units at `price`, rounded down as the vault
+// issues them
+pub fn assets_to_shares(
+    e: &Env,
+    offer: &Offer,
+    price: &Price,
+    assets: i128,
+) -> Result<i128, Error> {
+    let exponent = offer.sell_decimals as i64 + price.decimals as i64 - offer.buy_decimals as i64;
+    mul_div_pow10(e, assets, 1, price.mantissa, exponent, Rounding::Floor)
+}
+
+// distance of `price` from `reference` in basis points of `reference`
+pub fn deviation_bps(e: &Env, price: &Price, reference: &Price) -> Result<i128, Error> {
+    // compare the mantissas at the larger of the two decimals
+    let decimals = price.decimals.max(reference.decimals);
+    let scaled = I256::from_i128(e, price.mantissa).mul(&pow10(e, decimals - price.decimals));
+    let reference_scaled =
+        I256::from_i128(e, reference.mantissa).mul(&pow10(e, decimals - reference.decimals));
+    let distance = if scaled > reference_scaled {
+        scaled.sub(&reference_scaled)
+    } else {
+        reference_scaled.sub(&scaled)
+    };
+    distance
+        .mul(&I256::from_i32(e, 10000))
+        .div(&reference_scaled)
+        .to_i128()
+        .ok_or(Error::Overflow)
+}
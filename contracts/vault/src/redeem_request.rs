@@ -1,36 +1,314 @@
+use crate::buy_token::is_base_token;
 use crate::errors::Error;
-use crate::storage_types::{DataKey, RedeemRequest};
+use crate::storage_types::{
+    DataKey, RedeemRequest, RequesterDataKey, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
 
-use soroban_sdk::{panic_with_error, Address, Env};
+use soroban_sdk::{Address, Env, IntoVal, Val, Vec};
 
-pub fn read_redeem_request(e: &Env, sender: Address) -> RedeemRequest {
-    let key = DataKey::Request(sender.clone());
-    if let Some(request) = e.storage().instance().get::<_, RedeemRequest>(&key) {
-        request
+fn request_key(e: &Env, sender: Address, token: &Address, epoch_id: u32) -> DataKey {
+    if is_base_token(e, token) {
+        DataKey::EpochRequest(sender, epoch_id)
+    } else {
+        DataKey::TokenRequest(sender, token.clone(), epoch_id)
+    }
+}
+
+fn epochs_key(e: &Env, sender: Address, token: &Address) -> DataKey {
+    if is_base_token(e, token) {
+        DataKey::RequestEpochs(sender)
     } else {
-        RedeemRequest {
+        DataKey::TokenRequestEpochs(sender, token.clone())
+    }
+}
+
+pub fn read_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: &Address,
+    epoch_id: u32,
+) -> RedeemRequest {
+    let key = request_key(e, sender.clone(), token, epoch_id);
+    if let Some(request) = e.storage().persistent().get::<_, RedeemRequest>(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        return request;
+    }
+    match read_legacy_request(e, &sender, token) {
+        Some(request) if request.epoch_id == epoch_id => request,
+        _ => RedeemRequest {
             shares_amount: 0,
-            epoch_id: 0,
-        }
+            epoch_id,
+        },
+    }
+}
+
+pub fn write_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: &Address,
+    amount: i128,
+    epoch_id: u32,
+) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::NegativeRedeemAmount);
     }
+    store_redeem_request(e, sender, token, amount, epoch_id)
 }
 
-pub fn write_redeem_request(e: &Env, sender: Address, amount: i128, epoch_id: u32) {
+pub fn delete_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: &Address,
+    epoch_id: u32,
+) -> Result<(), Error> {
+    store_redeem_request(e, sender, token, 0, epoch_id)
+}
+
+fn store_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: &Address,
+    amount: i128,
+    epoch_id: u32,
+) -> Result<(), Error> {
+    migrate_legacy_request(e, &sender, token)?;
+    let key = request_key(e, sender.clone(), token, epoch_id);
+    let mut epochs = read_request_epochs(e, sender.clone(), token);
+    let index = epochs.binary_search(epoch_id);
+    if amount == 0 {
+        e.storage().persistent().remove(&key);
+        if let Ok(index) = index {
+            epochs.remove(index);
+            write_request_epochs(e, sender.clone(), token, &epochs);
+            remove_epoch_requester(e, token, epoch_id, sender)?;
+        }
+        return Ok(());
+    }
     let request = RedeemRequest {
         shares_amount: amount,
         epoch_id,
     };
-    if amount < 0 {
-        panic_with_error!(e, Error::NegativeRedeemAmount);
+    e.storage().persistent().set(&key, &request);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    if let Err(index) = index {
+        epochs.insert(index, epoch_id);
+        write_request_epochs(e, sender.clone(), token, &epochs);
+        add_epoch_requester(e, token, epoch_id, sender)?;
+    }
+    Ok(())
+}
+
+fn write_requester_entry<V: IntoVal<Env, Val>>(e: &Env, key: RequesterDataKey, value: &V) {
+    let key = DataKey::EpochRequester(key);
+    e.storage().persistent().set(&key, value);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+// senders with a pending or unclaimed request paid in `token` in the epoch, so keepers can
+// find whom to process claims for. A sender is listed once, and is removed again when the
+// request is claimed or cancelled.
+fn add_epoch_requester(
+    e: &Env,
+    token: &Address,
+    epoch_id: u32,
+    sender: Address,
+) -> Result<(), Error> {
+    let count = read_epoch_requester_count(e, token, epoch_id);
+    write_requester_entry(
+        e,
+        RequesterDataKey::Entry(token.clone(), epoch_id, count),
+        &sender,
+    );
+    write_requester_entry(
+        e,
+        RequesterDataKey::Position(token.clone(), epoch_id, sender),
+        &count,
+    );
+    write_requester_entry(
+        e,
+        RequesterDataKey::Count(token.clone(), epoch_id),
+        &count.checked_add(1).ok_or(Error::Overflow)?,
+    );
+    Ok(())
+}
+
+// the last entry takes the place of the removed one, so the index stays without gaps
+fn remove_epoch_requester(
+    e: &Env,
+    token: &Address,
+    epoch_id: u32,
+    sender: Address,
+) -> Result<(), Error> {
+    let position_key =
+        DataKey::EpochRequester(RequesterDataKey::Position(token.clone(), epoch_id, sender));
+    let Some(position) = e.storage().persistent().get::<_, u32>(&position_key) else {
+        return Ok(());
+    };
+    e.storage().persistent().remove(&position_key);
+    let last = read_epoch_requester_count(e, token, epoch_id)
+        .checked_sub(1)
+        .ok_or(Error::Overflow)?;
+    let last_key = DataKey::EpochRequester(RequesterDataKey::Entry(token.clone(), epoch_id, last));
+    if let Some(moved) = e
+        .storage()
+        .persistent()
+        .get::<_, Address>(&last_key)
+        .filter(|_| position != last)
+    {
+        write_requester_entry(
+            e,
+            RequesterDataKey::Entry(token.clone(), epoch_id, position),
+            &moved,
+        );
+        write_requester_entry(
+            e,
+            RequesterDataKey::Position(token.clone(), epoch_id, moved),
+            &position,
+        );
+    }
+    e.storage().persistent().remove(&last_key);
+    let count_key = DataKey::EpochRequester(RequesterDataKey::Count(token.clone(), epoch_id));
+    if last == 0 {
+        e.storage().persistent().remove(&count_key);
+    } else {
+        write_requester_entry(e, RequesterDataKey::Count(token.clone(), epoch_id), &last);
+    }
+    Ok(())
+}
+
+pub fn read_epoch_requester_count(e: &Env, token: &Address, epoch_id: u32) -> u32 {
+    e.storage()
+        .persistent()
+        .get(&DataKey::EpochRequester(RequesterDataKey::Count(
+            token.clone(),
+            epoch_id,
+        )))
+        .unwrap_or(0)
+}
+
+// up to `limit` requesters of the epoch starting at position `from`
+pub fn read_epoch_requesters(
+    e: &Env,
+    token: &Address,
+    epoch_id: u32,
+    from: u32,
+    limit: u32,
+) -> Vec<Address> {
+    let count = read_epoch_requester_count(e, token, epoch_id);
+    let to = from.saturating_add(limit).min(count);
+    let mut requesters = Vec::new(e);
+    for index in from..to {
+        let key = DataKey::EpochRequester(RequesterDataKey::Entry(token.clone(), epoch_id, index));
+        if let Some(sender) = e.storage().persistent().get::<_, Address>(&key) {
+            requesters.push_back(sender);
+        }
+    }
+    requesters
+}
+
+// epochs the sender has a pending redeem request paid in `token` in, in ascending order
+pub fn read_request_epochs(e: &Env, sender: Address, token: &Address) -> Vec<u32> {
+    let key = epochs_key(e, sender.clone(), token);
+    let mut epochs = if let Some(epochs) = e.storage().persistent().get::<_, Vec<u32>>(&key) {
+        e.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        epochs
+    } else {
+        Vec::new(e)
+    };
+    if let Some(request) = read_legacy_request(e, &sender, token) {
+        if let Err(index) = epochs.binary_search(request.epoch_id) {
+            epochs.insert(index, request.epoch_id);
+        }
+    }
+    epochs
+}
+
+fn write_request_epochs(e: &Env, sender: Address, token: &Address, epochs: &Vec<u32>) {
+    let key = epochs_key(e, sender, token);
+    if epochs.is_empty() {
+        e.storage().persistent().remove(&key);
+        return;
+    }
+    e.storage().persistent().set(&key, epochs);
+    e.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+pub fn extend_redeem_requests(e: &Env, sender: Address, token: &Address) {
+    let key = epochs_key(e, sender.clone(), token);
+    if !e.storage().persistent().has(&key) {
+        return;
+    }
+    for epoch_id in read_request_epochs(e, sender.clone(), token).iter() {
+        e.storage().persistent().extend_ttl(
+            &request_key(e, sender.clone(), token, epoch_id),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+    }
+}
+
+// mark the vault as holding requests of schema version 3 or older
+pub fn write_legacy_requests(e: &Env) {
+    e.storage().instance().set(&DataKey::LegacyRequests, &true);
+}
+
+// up to schema version 3 a sender had a single request, in instance storage on version 2
+// and in persistent storage on version 3, always paid in the offer's buy token. Reads decode
+// it in place, it is moved to its epoch the first time the sender's requests are written.
+fn read_legacy_request(e: &Env, sender: &Address, token: &Address) -> Option<RedeemRequest> {
+    if !e.storage().instance().has(&DataKey::LegacyRequests) || !is_base_token(e, token) {
+        return None;
     }
     let key = DataKey::Request(sender.clone());
-    e.storage().instance().set(&key, &request);
+    e.storage()
+        .persistent()
+        .get::<_, RedeemRequest>(&key)
+        .or_else(|| e.storage().instance().get::<_, RedeemRequest>(&key))
+        .filter(|request| request.shares_amount > 0)
 }
 
-pub fn delete_redeem_request(e: &Env, sender: Address, epoch_id: u32) {
-    let request = read_redeem_request(e, sender.clone());
-    if request.epoch_id > epoch_id {
-        panic_with_error!(e, Error::InvalidEpochId);
+fn migrate_legacy_request(e: &Env, sender: &Address, token: &Address) -> Result<(), Error> {
+    if !e.storage().instance().has(&DataKey::LegacyRequests) || !is_base_token(e, token) {
+        return Ok(());
+    }
+    let key = DataKey::Request(sender.clone());
+    let request = if let Some(request) = e.storage().persistent().get::<_, RedeemRequest>(&key) {
+        e.storage().persistent().remove(&key);
+        request
+    } else if let Some(request) = e.storage().instance().get::<_, RedeemRequest>(&key) {
+        e.storage().instance().remove(&key);
+        request
+    } else {
+        return Ok(());
+    };
+    if request.shares_amount > 0 {
+        store_redeem_request(
+            e,
+            sender.clone(),
+            token,
+            request.shares_amount,
+            request.epoch_id,
+        )?;
     }
-    write_redeem_request(e, sender, 0, epoch_id);
+    Ok(())
 }
@@ -0,0 +1,40 @@
+use crate::errors::Error;
+use crate::storage_types::DataKey;
+use soroban_sdk::{token, Address, Env};
+
+// tokens the vault holds for investors: escrowed redeem shares, settled deposit shares not
+// claimed yet, escrowed deposits, and buy tokens funded for claims or left as dust
+pub fn read_reserved(e: &Env, token: &Address) -> i128 {
+    e.storage()
+        .instance()
+        .get(&DataKey::Reserved(token.clone()))
+        .unwrap_or(0)
+}
+
+fn write_reserved(e: &Env, token: &Address, amount: i128) {
+    let key = DataKey::Reserved(token.clone());
+    if amount > 0 {
+        e.storage().instance().set(&key, &amount);
+    } else {
+        e.storage().instance().remove(&key);
+    }
+}
+
+pub fn reserve(e: &Env, token: &Address, amount: i128) -> Result<(), Error> {
+    let reserved = read_reserved(e, token)
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    write_reserved(e, token, reserved);
+    Ok(())
+}
+
+// never goes below zero, tokens of a vault migrated with rounding left over are simply freed
+pub fn unreserve(e: &Env, token: &Address, amount: i128) {
+    write_reserved(e, token, read_reserved(e, token).saturating_sub(amount));
+}
+
+// what the vault holds beyond its reserves, the most `claim_leftover` can take
+pub fn read_surplus(e: &Env, token: &Address) -> i128 {
+    let balance = token::Client::new(e, token).balance(&e.current_contract_address());
+    balance.saturating_sub(read_reserved(e, token)).max(0)
+}
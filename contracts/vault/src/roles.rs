@@ -0,0 +1,55 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, Role};
+use soroban_sdk::{Address, Env};
+
+pub fn has_role(e: &Env, role: Role, account: &Address) -> bool {
+    e.storage()
+        .instance()
+        .has(&DataKey::Role(role, account.clone()))
+}
+
+pub fn write_role(e: &Env, role: Role, account: &Address) {
+    e.storage()
+        .instance()
+        .set(&DataKey::Role(role, account.clone()), &true);
+}
+
+pub fn remove_role(e: &Env, role: Role, account: &Address) {
+    e.storage()
+        .instance()
+        .remove(&DataKey::Role(role, account.clone()));
+}
+
+// require the account to sign and to hold the given role
+pub fn require_role(e: &Env, role: Role, account: &Address) -> Result<(), Error> {
+    account.require_auth();
+    if !has_role(e, role, account) {
+        return Err(Error::Unauthorized);
+    }
+    Ok(())
+}
+
+// roles granted with the offer to its seller and to its treasury
+pub const SELLER_ROLES: [Role; 3] = [Role::PriceUpdater, Role::Pauser, Role::Sweeper];
+pub const TREASURY_ROLES: [Role; 1] = [Role::Settler];
+
+// the admin manages roles, the seller runs the offer and the treasury settles epochs
+pub fn write_initial_roles(e: &Env, admin: &Address, seller: &Address, treasury: &Address) {
+    write_role(e, Role::Admin, admin);
+    for role in SELLER_ROLES {
+        write_role(e, role, seller);
+    }
+    for role in TREASURY_ROLES {
+        write_role(e, role, treasury);
+    }
+}
+
+// hand the roles the replaced seller or treasury still holds over to its successor
+pub fn move_roles(e: &Env, roles: &[Role], from: &Address, to: &Address) {
+    for role in roles {
+        if has_role(e, *role, from) {
+            remove_role(e, *role, from);
+            write_role(e, *role, to);
+        }
+    }
+}
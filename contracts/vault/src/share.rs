@@ -0,0 +1,118 @@
+use crate::balance::{burn_balance, mint_balance, receive_balance, spend_balance};
+use crate::errors::Error;
+use crate::reserve::{read_surplus, reserve, unreserve};
+use crate::storage_types::Offer;
+use soroban_sdk::{symbol_short, token, Address, Env, Symbol};
+use soroban_token_sdk::metadata::TokenMetadata;
+use soroban_token_sdk::TokenUtils;
+
+// instance key soroban-token-sdk keeps the token metadata under
+const METADATA_KEY: Symbol = symbol_short!("METADATA");
+
+// the vault issues its own shares when it is the offer's sell token, otherwise it hands out
+// and takes back a pre-minted sell token
+pub fn is_share_token(e: &Env, offer: &Offer) -> bool {
+    offer.sell_token == e.current_contract_address()
+}
+
+// decimals of the shares, if their metadata is set
+pub fn read_share_decimals(e: &Env) -> Option<u32> {
+    e.storage()
+        .instance()
+        .get::<_, TokenMetadata>(&METADATA_KEY)
+        .map(|metadata| metadata.decimal)
+}
+
+// hand new shares to an investor, minted or taken from the vault's sell tokens
+pub fn issue_shares(e: &Env, offer: &Offer, to: &Address, amount: i128) -> Result<(), Error> {
+    let contract_address = e.current_contract_address();
+    if is_share_token(e, offer) {
+        mint_balance(e, to.clone(), amount)?;
+        TokenUtils::new(e)
+            .events()
+            .mint(contract_address, to.clone(), amount);
+    } else {
+        pay_out(e, &offer.sell_token, to, amount)?;
+    }
+    Ok(())
+}
+
+// send tokens held by the vault beyond its reserves, failing with `InsufficientLiquidity` when
+// the surplus is too small. Callers paying out reserved tokens unreserve them first.
+pub fn pay_out(e: &Env, token: &Address, to: &Address, amount: i128) -> Result<(), Error> {
+    if read_surplus(e, token) < amount {
+        return Err(Error::InsufficientLiquidity);
+    }
+    token::Client::new(e, token).transfer(&e.current_contract_address(), to, &amount);
+    Ok(())
+}
+
+// escrow shares of an investor in the vault, the investor's authorization is checked by the caller
+pub fn escrow_shares(e: &Env, offer: &Offer, from: &Address, amount: i128) -> Result<(), Error> {
+    move_shares(e, offer, from, &e.current_contract_address(), amount)?;
+    reserve(e, &offer.sell_token, amount)
+}
+
+// escrow shares of an investor on behalf of an operator. A pre-minted sell token is taken
+// through the investor's allowance to the vault.
+pub fn escrow_approved_shares(
+    e: &Env,
+    offer: &Offer,
+    from: &Address,
+    amount: i128,
+) -> Result<(), Error> {
+    let contract_address = e.current_contract_address();
+    if is_share_token(e, offer) {
+        move_shares(e, offer, from, &contract_address, amount)?;
+    } else {
+        token::Client::new(e, &offer.sell_token).transfer_from(
+            &contract_address,
+            from,
+            &contract_address,
+            &amount,
+        );
+    }
+    reserve(e, &offer.sell_token, amount)
+}
+
+// give escrowed shares back to an investor
+pub fn release_shares(e: &Env, offer: &Offer, to: &Address, amount: i128) -> Result<(), Error> {
+    unreserve(e, &offer.sell_token, amount);
+    move_shares(e, offer, &e.current_contract_address(), to, amount)
+}
+
+// escrowed shares redeemed at settlement are burned, or sent to the seller
+pub fn retire_shares(e: &Env, offer: &Offer, amount: i128) -> Result<(), Error> {
+    unreserve(e, &offer.sell_token, amount);
+    let contract_address = e.current_contract_address();
+    if is_share_token(e, offer) {
+        burn_balance(e, contract_address.clone(), amount)?;
+        TokenUtils::new(e).events().burn(contract_address, amount);
+    } else {
+        token::Client::new(e, &offer.sell_token).transfer(
+            &contract_address,
+            &offer.seller,
+            &amount,
+        );
+    }
+    Ok(())
+}
+
+fn move_shares(
+    e: &Env,
+    offer: &Offer,
+    from: &Address,
+    to: &Address,
+    amount: i128,
+) -> Result<(), Error> {
+    if is_share_token(e, offer) {
+        spend_balance(e, from.clone(), amount)?;
+        receive_balance(e, to.clone(), amount)?;
+        TokenUtils::new(e)
+            .events()
+            .transfer(from.clone(), to.clone(), amount);
+    } else {
+        token::Client::new(e, &offer.sell_token).transfer(from, to, &amount);
+    }
+    Ok(())
+}
@@ -1,5 +1,13 @@
+use crate::oracle::Asset;
 use soroban_sdk::{contracttype, Address};
 
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub(crate) const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
 #[derive(Clone)]
 #[contracttype]
 pub struct RedeemRequest {
@@ -7,21 +15,271 @@ pub struct RedeemRequest {
     pub epoch_id: u32,       // epoch id of the request
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositRequest {
+    pub assets_amount: i128, // amount of buy tokens to deposit
+    pub epoch_id: u32,       // epoch id of the request
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceDataKey {
+    pub from: Address,
+    pub spender: Address,
+}
+
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+// entries of the index of senders with a redeem request paid in a token in an epoch
+#[derive(Clone)]
+#[contracttype]
+pub enum RequesterDataKey {
+    Entry(Address, u32, u32),        // token, epoch, position
+    Count(Address, u32),             // token, epoch
+    Position(Address, u32, Address), // token, epoch, sender
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Price {
+    pub mantissa: i128, // price of a whole sell token in whole buy tokens, times 10^decimals
+    pub decimals: u32,  // decimals of the mantissa
+}
+
 #[contracttype]
 pub struct Offer {
     pub seller: Address,     // owner of the vault
     pub treasury: Address,   // treasury address
     pub sell_token: Address, // token to be sold
     pub buy_token: Address,  // token to be bought
-    pub price: u32,          // price of the sell token in buy token
+    pub price: Price,        // price of the sell token in buy token
+    pub sell_decimals: u32,  // decimals of the sell token
+    pub buy_decimals: u32,   // decimals of the buy token
+}
+
+// offer up to schema version 4, priced with 6 decimals regardless of the token decimals
+#[contracttype]
+pub struct LegacyOffer {
+    pub seller: Address,
+    pub treasury: Address,
+    pub sell_token: Address,
+    pub buy_token: Address,
+    pub price: u32,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochFill {
+    pub fill_ratio: u32, // part of the requested shares filled at settlement, 1000000 being all
+    pub shares_left: i128, // requested shares of the epoch not claimed yet
+    pub filled_left: i128, // filled shares of the epoch not claimed yet
+    pub assets_left: i128, // buy tokens funded for the epoch not paid out yet
+}
+
+// record of an epoch's redemptions in a buy token, kept after every claim
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochInfo {
+    pub epoch_id: u32,
+    pub total_redeem: i128, // shares requested in the epoch, carried over ones included
+    pub redeemed_shares: i128, // shares filled and retired at settlement
+    pub funded_asset: i128, // buy tokens the treasury funded the epoch with
+    pub claimed_asset: i128, // buy tokens paid out by claims so far, exit fees included
+    pub price: Price,       // price the epoch was settled at
+    pub settled_at: u64,    // ledger timestamp of the settlement
+}
+
+// settled redemptions up to schema version 5
+#[contracttype]
+pub struct LegacyEpochFill {
+    pub fill_ratio: u32,
+    pub shares_left: i128,
+    pub filled_left: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochDeposit {
+    pub rate: Price,       // price the deposits of the epoch were settled at
+    pub assets_left: i128, // deposited buy tokens of the epoch not claimed yet
+    pub shares_left: i128, // shares of the epoch not claimed yet
+}
+
+// settled deposits up to schema version 4
+#[contracttype]
+pub struct LegacyEpochDeposit {
+    pub rate: u32,
+    pub assets_left: i128,
+    pub shares_left: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SettlementPreview {
+    pub epoch_id: u32,        // epoch to be settled
+    pub price: Price,         // price the epoch would be settled at
+    pub total_deposit: i128,  // buy tokens of the epoch's deposit requests
+    pub deposit_shares: i128, // shares issued for the deposit requests
+    pub deposit_fee: i128,    // entry fee taken from the deposit requests
+    pub total_redeem: i128,   // shares requested to redeem in the epoch
+    pub filled_shares: i128,  // requested shares the treasury can fund
+    pub funded_asset: i128,   // buy tokens taken from the treasury
+    pub fill_ratio: u32,      // part of the requested shares filled, 1000000 being all
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ComplianceConfig {
+    pub enforced: bool,            // whether opening a position needs the allowlist
+    pub registry: Option<Address>, // external registry verifying accounts not on the allowlist
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochSchedule {
+    pub period: u64,            // seconds between the starts of two epochs
+    pub cutoff_offset: u64,     // seconds after the start until redeem requests are accepted
+    pub settlement_offset: u64, // seconds after the start until the epoch can be settled
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochWindow {
+    pub start: u64,        // ledger timestamp the epoch started at
+    pub cutoff: u64,       // last timestamp for redeem requests in this epoch
+    pub settle_after: u64, // earliest timestamp the epoch can be settled at
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct Fees {
+    pub entry_fee_bps: u32, // fee on deposited buy tokens
+    pub exit_fee_bps: u32,  // fee on claimed buy tokens
+    pub recipient: Address, // receiver of the fees
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Limits {
+    pub min_deposit: i128,      // smallest buy token amount per deposit
+    pub max_deposit: i128,      // largest buy token amount per deposit, 0 for no limit
+    pub min_redeem: i128,       // smallest share amount per redeem request
+    pub max_redeem: i128,       // largest share amount per redeem request, 0 for no limit
+    pub user_deposit_cap: i128, // buy tokens a user can deposit in total, 0 for no limit
+    pub user_redeem_cap: i128,  // shares a user can request to redeem in total, 0 for no limit
+    pub epoch_redeem_cap: i128, // shares that can be requested in an epoch, 0 for no limit
+    pub tvl_cap: i128,          // buy tokens the vault can hold in total, 0 for no limit
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OracleConfig {
+    pub oracle: Address,        // SEP-40 price feed contract
+    pub asset: Asset,           // asset quoted in buy token by the price feed
+    pub max_age: u64,           // seconds after which a quote is stale
+    pub max_deviation_bps: u32, // max distance of a quote from the stored price
+    pub allow_fallback: bool,   // use the stored price instead of failing on a bad quote
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BuyToken {
+    pub price: Price,      // price of the sell token in this token
+    pub decimals: u32,     // decimals of this token
+    pub treasury: Address, // receives the deposits and funds the redemptions in this token
+    pub enabled: bool,     // whether deposits and redeem requests are accepted
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PendingTransfer {
+    pub new_address: Address, // proposed address
+    pub expires_at: u64,      // ledger timestamp after which it can't be accepted
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Role {
+    Admin,        // grants and revokes roles
+    PriceUpdater, // updates the offer price
+    Settler,      // settles epochs
+    Pauser,       // pauses and unpauses operations
+    Sweeper,      // claims leftover tokens
+    Compliance,   // manages the allowlist and the denylist
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Operation {
+    Deposit,       // deposit
+    RedeemRequest, // redeem_request
+    Claim,         // claim_request
+    Settlement,    // setle_epoch
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum OperatorScope {
+    Redeem, // redeem_request_for
+    Cancel, // cancel_request_for
+    Claim,  // claim_request_for
 }
 
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
-    Request(Address),
+    Request(Address), // single request per sender up to schema version 3
     RedeemRate(u32),
     Offer,
     TotalRedeem,
     EpochId,
+    Role(Role, Address),
+    PendingSeller,
+    PendingTreasury,
+    SchemaVersion,
+    Paused(Operation),
+    Oracle,
+    Fees,
+    Schedule,
+    EpochStart(u32),
+    NextTotalRedeem,
+    EpochFill(u32),
+    DepositRequest(Address),
+    TotalDeposit,
+    NextTotalDeposit,
+    EpochDeposit(u32),
+    EpochRequest(Address, u32),
+    RequestEpochs(Address),
+    LegacyRequests,
+    Balance(Address),
+    Allowance(AllowanceDataKey),
+    TotalSupply,
+    Compliance,
+    Allowed(Address),
+    Denied(Address),
+    Limits,
+    UserDeposited(Address),
+    UserRedeemed(Address),
+    TotalAssets,
+    Dust,
+    BuyToken(Address),
+    BuyTokens,
+    TokenTotalRedeem(Address),
+    TokenNextTotalRedeem(Address),
+    TokenRedeemRate(Address, u32),
+    TokenEpochFill(Address, u32),
+    TokenRequest(Address, Address, u32),  // sender, token, epoch
+    TokenRequestEpochs(Address, Address), // sender, token
+    TokenDust(Address),
+    TokenOracle(Address),
+    Reserved(Address),
+    Operator(Address, Address, OperatorScope), // owner, operator, scope
+    Receiver(Address, Address),                // owner, receiver
+    EpochRequester(RequesterDataKey),
+    EpochInfo(Address, u32), // token, epoch
 }
@@ -1,13 +1,36 @@
 #![cfg(test)]
 extern crate std;
 
+use crate::errors::Error;
+use crate::math::{mul_div_ceil, mul_div_floor};
+use crate::offer::load_offer;
+use crate::oracle::{Asset, PriceData};
+use crate::storage_types::{
+    BuyToken, ComplianceConfig, DataKey, EpochDeposit, EpochFill, EpochSchedule, EpochWindow,
+    LegacyEpochDeposit, LegacyEpochFill, LegacyOffer, Limits, Operation, OperatorScope,
+    OracleConfig, Price, RedeemRequest, Role, SettlementPreview, DAY_IN_LEDGERS,
+    INSTANCE_BUMP_AMOUNT, PERSISTENT_BUMP_AMOUNT,
+};
+use crate::upgrade::SCHEMA_VERSION;
 use crate::vault::VaultClient;
 use soroban_sdk::{
-    symbol_short,
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
-    token, Address, Env, IntoVal, Symbol,
+    contract, contractimpl, symbol_short,
+    testutils::{
+        storage::{Instance as _, Persistent as _},
+        Address as _, AuthorizedFunction, AuthorizedInvocation, Events, Ledger, MockAuth,
+        MockAuthInvoke,
+    },
+    token, vec, Address, Env, IntoVal, String, Symbol,
 };
 
+// price with 6 decimals
+fn price(mantissa: i128) -> Price {
+    Price {
+        mantissa,
+        decimals: 6,
+    }
+}
+
 fn create_token_contract<'a>(
     e: &Env,
     admin: &Address,
@@ -19,13 +42,54 @@ fn create_token_contract<'a>(
     )
 }
 
+// rewrite the offer as stored up to schema version 4
+fn write_legacy_offer(e: &Env, price: u32) {
+    let offer = load_offer(e).unwrap();
+    e.storage().instance().set(
+        &DataKey::Offer,
+        &LegacyOffer {
+            seller: offer.seller,
+            treasury: offer.treasury,
+            sell_token: offer.sell_token,
+            buy_token: offer.buy_token,
+            price,
+        },
+    );
+}
+
+#[cfg(not(feature = "legacy-initialize"))]
+fn create_vault_contract<'a>(
+    e: &Env,
+    seller: &Address,
+    treasury: &Address,
+    sell_token: &Address,
+    buy_token: &Address,
+    initial_price: &Price,
+) -> VaultClient<'a> {
+    VaultClient::new(
+        e,
+        &e.register(
+            crate::vault::Vault,
+            (
+                seller,
+                seller,
+                treasury,
+                sell_token.clone(),
+                buy_token.clone(),
+                initial_price.clone(),
+            ),
+        ),
+    )
+}
+
+#[cfg(feature = "legacy-initialize")]
 fn create_vault_contract<'a>(
     e: &Env,
     seller: &Address,
     treasury: &Address,
     sell_token: &Address,
     buy_token: &Address,
-    initial_price: &u32,
+    initial_price: &Price,
 ) -> VaultClient<'a> {
     let vault = VaultClient::new(e, &e.register(crate::vault::Vault, ()));
     vault.initialize(seller, treasury, sell_token, buy_token, initial_price);
@@ -36,7 +100,7 @@ fn create_vault_contract<'a>(
             AuthorizedInvocation {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
-                    Symbol::new(&e, "initialize"),
+                    Symbol::new(e, "initialize"),
                     (
                         seller,
                         treasury,
@@ -50,6 +114,10 @@ fn create_vault_contract<'a>(
             }
         )]
     );
+    assert_eq!(
+        vault.try_initialize(seller, treasury, sell_token, buy_token, initial_price),
+        Err(Ok(Error::OfferAlreadyCreated))
+    );
 
     vault
 }
@@ -78,28 +146,31 @@ fn test() {
         &treasury,
         &sell_token_client.address,
         &buy_token_client.address,
-        &1000000,
+        &price(1000000),
     );
 
     let offer = vault.get_offer();
 
-    assert_eq!(offer.price, 1000000);
+    assert_eq!(offer.price, price(1000000));
     assert_eq!(offer.buy_token, buy_token_client.address);
     assert_eq!(offer.sell_token, sell_token_client.address);
     assert_eq!(offer.seller, seller);
     assert_eq!(offer.treasury, treasury);
 
     assert_eq!(vault.get_epoch_id(), 1_u32);
-    assert_eq!(vault.get_total_redeem(), 0_i128);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 0_i128);
 
     sell_token_admin.mint(&seller, &1000);
     buy_token_admin.mint(&buyer, &1000);
 
     sell_token_client.transfer(&seller, &vault.address, &100);
 
-    assert!(vault.try_deposit(&buyer, &10_i128, &11_i128).is_err());
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &10_i128, &11_i128),
+        Err(Ok(Error::PriceTooLow))
+    );
 
-    vault.deposit(&buyer, &10_i128, &10_i128);
+    vault.deposit(&buyer, &buy_token_client.address, &10_i128, &10_i128);
 
     assert_eq!(
         e.auths(),
@@ -109,7 +180,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     symbol_short!("deposit"),
-                    (&buyer, 10_i128, 10_i128).into_val(&e)
+                    (&buyer, &buy_token_client.address, 10_i128, 10_i128).into_val(&e)
                 )),
                 sub_invocations: std::vec![AuthorizedInvocation {
                     function: AuthorizedFunction::Contract((
@@ -131,7 +202,7 @@ fn test() {
     assert_eq!(buy_token_client.balance(&buyer), 990);
     assert_eq!(buy_token_client.balance(&vault.address), 0);
 
-    vault.claim_leftover(&sell_token_client.address, &90);
+    vault.claim_leftover(&seller, &sell_token_client.address, &90);
 
     assert_eq!(
         e.auths(),
@@ -141,7 +212,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     Symbol::new(&e, "claim_leftover"),
-                    (&sell_token_client.address, 90_i128).into_val(&e)
+                    (&seller, &sell_token_client.address, 90_i128).into_val(&e)
                 )),
                 sub_invocations: std::vec![]
             }
@@ -151,7 +222,7 @@ fn test() {
     assert_eq!(sell_token_client.balance(&seller), 990);
     assert_eq!(sell_token_client.balance(&vault.address), 0);
 
-    vault.redeem_request(&buyer, &5_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
 
     assert_eq!(
         e.auths(),
@@ -161,7 +232,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     Symbol::new(&e, "redeem_request"),
-                    (buyer.clone(), 5_i128).into_val(&e)
+                    (buyer.clone(), &buy_token_client.address, 5_i128).into_val(&e)
                 )),
                 sub_invocations: std::vec![AuthorizedInvocation {
                     function: AuthorizedFunction::Contract((
@@ -177,13 +248,13 @@ fn test() {
 
     assert_eq!(sell_token_client.balance(&buyer), 5);
     assert_eq!(sell_token_client.balance(&vault.address), 5);
-    assert_eq!(vault.get_total_redeem(), 5);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 5);
 
-    let redeem_request = vault.get_request(&buyer);
+    let redeem_request = vault.get_request(&buyer, &buy_token_client.address, &1);
     assert_eq!(redeem_request.shares_amount, 5);
     assert_eq!(redeem_request.epoch_id, 1);
 
-    vault.setle_epoch();
+    vault.setle_epoch(&treasury);
     assert_eq!(
         e.auths(),
         std::vec![(
@@ -192,7 +263,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     Symbol::new(&e, "setle_epoch"),
-                    ().into_val(&e)
+                    (treasury.clone(),).into_val(&e)
                 )),
                 sub_invocations: std::vec![AuthorizedInvocation {
                     function: AuthorizedFunction::Contract((
@@ -207,11 +278,14 @@ fn test() {
     );
 
     assert_eq!(vault.get_epoch_id(), 2);
-    assert_eq!(vault.get_total_redeem(), 0);
-    assert_eq!(vault.get_redeem_rate(&1), 1000000);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 0);
+    assert_eq!(
+        vault.get_redeem_rate(&buy_token_client.address, &1),
+        price(1000000)
+    );
     assert_eq!(sell_token_client.balance(&seller), 995);
 
-    vault.claim_request(&buyer);
+    vault.claim_request(&buyer, &buy_token_client.address);
     assert_eq!(
         e.auths(),
         std::vec![(
@@ -220,7 +294,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     Symbol::new(&e, "claim_request"),
-                    (buyer.clone(),).into_val(&e)
+                    (buyer.clone(), &buy_token_client.address).into_val(&e)
                 )),
                 sub_invocations: std::vec![]
             }
@@ -228,9 +302,14 @@ fn test() {
     );
     assert_eq!(buy_token_client.balance(&buyer), 995);
     assert_eq!(buy_token_client.balance(&vault.address), 0);
-    assert_eq!(vault.get_request(&buyer).shares_amount, 0);
+    assert_eq!(
+        vault
+            .get_request(&buyer, &buy_token_client.address, &1)
+            .shares_amount,
+        0
+    );
 
-    vault.redeem_request(&buyer, &5_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
     assert_eq!(
         e.auths(),
         std::vec![(
@@ -239,7 +318,7 @@ fn test() {
                 function: AuthorizedFunction::Contract((
                     vault.address.clone(),
                     Symbol::new(&e, "redeem_request"),
-                    (buyer.clone(), 5_i128).into_val(&e)
+                    (buyer.clone(), &buy_token_client.address, 5_i128).into_val(&e)
                 )),
                 sub_invocations: std::vec![AuthorizedInvocation {
                     function: AuthorizedFunction::Contract((
@@ -255,7 +334,7 @@ fn test() {
 
     assert_eq!(sell_token_client.balance(&buyer), 0);
     assert_eq!(sell_token_client.balance(&vault.address), 5);
-    assert_eq!(vault.get_total_redeem(), 5);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 5);
 
     vault.cancel_request(&buyer);
     assert_eq!(
@@ -275,5 +354,3248 @@ fn test() {
 
     assert_eq!(sell_token_client.balance(&buyer), 5);
     assert_eq!(sell_token_client.balance(&vault.address), 0);
-    assert_eq!(vault.get_total_redeem(), 0);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 0);
+}
+
+#[test]
+fn test_roles() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let price_bot = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, _) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+
+    assert!(vault.has_role(&Role::Admin, &seller));
+    assert!(vault.has_role(&Role::PriceUpdater, &seller));
+    assert!(vault.has_role(&Role::Pauser, &seller));
+    assert!(vault.has_role(&Role::Sweeper, &seller));
+    assert!(vault.has_role(&Role::Settler, &treasury));
+    assert!(!vault.has_role(&Role::Admin, &treasury));
+
+    assert_eq!(
+        vault.try_updt_price(&price_bot, &price(2000000)),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    vault.grant_role(&seller, &Role::PriceUpdater, &price_bot);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            seller.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    vault.address.clone(),
+                    Symbol::new(&e, "grant_role"),
+                    (&seller, Role::PriceUpdater, &price_bot).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert!(vault.has_role(&Role::PriceUpdater, &price_bot));
+    assert_eq!(
+        vault.try_grant_role(&seller, &Role::PriceUpdater, &price_bot),
+        Err(Ok(Error::RoleAlreadyGranted))
+    );
+
+    vault.updt_price(&price_bot, &price(2000000));
+    assert_eq!(vault.get_offer().price, price(2000000));
+
+    // the price updater cannot sweep funds or manage roles
+    sell_token_admin.mint(&vault.address, &100);
+    assert_eq!(
+        vault.try_claim_leftover(&price_bot, &sell_token_client.address, &100),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_grant_role(&price_bot, &Role::Sweeper, &price_bot),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_setle_epoch(&price_bot),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    vault.revoke_role(&seller, &Role::PriceUpdater, &price_bot);
+    assert!(!vault.has_role(&Role::PriceUpdater, &price_bot));
+    assert_eq!(
+        vault.try_updt_price(&price_bot, &price(3000000)),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_revoke_role(&seller, &Role::PriceUpdater, &price_bot),
+        Err(Ok(Error::RoleNotGranted))
+    );
+
+    vault.renounce_role(&seller, &Role::Sweeper);
+    assert!(!vault.has_role(&Role::Sweeper, &seller));
+    assert_eq!(
+        vault.try_claim_leftover(&seller, &sell_token_client.address, &100),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_renounce_role(&seller, &Role::Sweeper),
+        Err(Ok(Error::RoleNotGranted))
+    );
+}
+
+#[test]
+fn test_transfer_seller_and_treasury() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let new_seller = Address::generate(&e);
+    let new_treasury = Address::generate(&e);
+
+    let (sell_token_client, _) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, _) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+
+    assert_eq!(vault.try_accept_seller(), Err(Ok(Error::NoPendingTransfer)));
+    assert_eq!(
+        vault.try_propose_seller(&treasury, &new_seller),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    vault.propose_seller(&seller, &new_seller);
+    let pending = vault.get_pending_seller().unwrap();
+    assert_eq!(pending.new_address, new_seller);
+    assert_eq!(pending.expires_at, e.ledger().timestamp() + 604800);
+
+    // the seller is unchanged until the proposal is accepted
+    assert_eq!(vault.get_offer().seller, seller);
+
+    vault.accept_seller();
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            new_seller.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    vault.address.clone(),
+                    Symbol::new(&e, "accept_seller"),
+                    ().into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert_eq!(vault.get_offer().seller, new_seller);
+    assert!(vault.get_pending_seller().is_none());
+
+    // the seller's roles move with it, the old key is retired
+    for role in [Role::PriceUpdater, Role::Pauser, Role::Sweeper] {
+        assert!(!vault.has_role(&role, &seller));
+        assert!(vault.has_role(&role, &new_seller));
+    }
+    assert_eq!(
+        vault.try_updt_price(&seller, &price(1100000)),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.updt_price(&new_seller, &price(1100000));
+    assert_eq!(vault.get_offer().price, price(1100000));
+
+    // a cancelled proposal can't be accepted
+    vault.propose_treasury(&seller, &new_treasury);
+    vault.cancel_treasury(&seller);
+    assert!(vault.get_pending_treasury().is_none());
+    assert_eq!(
+        vault.try_accept_treasury(),
+        Err(Ok(Error::NoPendingTransfer))
+    );
+    assert_eq!(
+        vault.try_cancel_treasury(&seller),
+        Err(Ok(Error::NoPendingTransfer))
+    );
+
+    // an expired proposal can't be accepted
+    vault.propose_treasury(&seller, &new_treasury);
+    e.ledger()
+        .set_timestamp(e.ledger().timestamp() + 604800 + 1);
+    assert_eq!(vault.try_accept_treasury(), Err(Ok(Error::TransferExpired)));
+    assert_eq!(vault.get_offer().treasury, treasury);
+
+    vault.propose_treasury(&seller, &new_treasury);
+    vault.accept_treasury();
+    assert_eq!(vault.get_offer().treasury, new_treasury);
+    assert_eq!(vault.get_offer().seller, new_seller);
+
+    // so do the treasury's
+    assert!(!vault.has_role(&Role::Settler, &treasury));
+    assert!(vault.has_role(&Role::Settler, &new_treasury));
+    assert_eq!(
+        vault.try_setle_epoch(&treasury),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.setle_epoch(&new_treasury);
+    assert_eq!(vault.get_epoch_id(), 2);
+}
+
+// the vault built with the `next-schema` feature, one schema version ahead
+const NEXT_VAULT_WASM: &[u8] = include_bytes!("../testdata/untangled_vault_next.wasm");
+
+#[test]
+fn test_upgrade() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION);
+
+    sell_token_admin.mint(&vault.address, &100);
+    buy_token_admin.mint(&buyer, &100);
+    vault.deposit(&buyer, &buy_token_client.address, &20_i128, &20_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
+
+    // the running code has nothing to convert
+    assert_eq!(vault.try_migrate(&seller), Err(Ok(Error::AlreadyMigrated)));
+
+    let wasm_hash = e.deployer().upload_contract_wasm(NEXT_VAULT_WASM);
+    assert_eq!(
+        vault.try_upgrade(&buyer, &wasm_hash),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    vault.upgrade(&seller, &wasm_hash);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            seller.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    vault.address.clone(),
+                    Symbol::new(&e, "upgrade"),
+                    (&seller, wasm_hash.clone()).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+
+    // the new code migrates the storage of the old one to its schema version
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION);
+    vault.migrate(&seller);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_migrated"), &seller).into_val(&e),
+                (SCHEMA_VERSION, SCHEMA_VERSION + 1).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION + 1);
+    assert_eq!(vault.try_migrate(&seller), Err(Ok(Error::AlreadyMigrated)));
+
+    // and reads back the storage and balances of the old one
+    assert_eq!(vault.get_reserved(&sell_token_client.address), 5);
+    assert_eq!(sell_token_client.balance(&vault.address), 85);
+    assert_eq!(sell_token_client.balance(&buyer), 15);
+    assert_eq!(buy_token_client.balance(&treasury), 20);
+
+    let offer = vault.get_offer();
+    assert_eq!(offer.seller, seller);
+    assert_eq!(offer.treasury, treasury);
+    assert_eq!(offer.price, price(1000000));
+    let request = vault.get_request(&buyer, &buy_token_client.address, &1);
+    assert_eq!(request.shares_amount, 5);
+    assert_eq!(request.epoch_id, 1);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 5);
+    assert_eq!(vault.get_epoch_id(), 1);
+    assert!(vault.has_role(&Role::Admin, &seller));
+
+    // and keeps serving the vault
+    vault.deposit(&buyer, &buy_token_client.address, &10_i128, &10_i128);
+    assert_eq!(sell_token_client.balance(&buyer), 25);
+    vault.setle_epoch(&treasury);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 75);
+}
+
+#[test]
+fn test_migrate() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    assert_eq!(vault.try_migrate(&seller), Err(Ok(Error::AlreadyMigrated)));
+
+    sell_token_admin.mint(&vault.address, &100);
+    buy_token_admin.mint(&buyer, &100);
+    vault.deposit(&buyer, &buy_token_client.address, &20_i128, &20_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
+
+    // rewrite the storage as a version 1 vault, which had no roles or schema version
+    e.as_contract(&vault.address, || {
+        let storage = e.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+        for role in [Role::Admin, Role::PriceUpdater, Role::Pauser, Role::Sweeper] {
+            storage.remove(&DataKey::Role(role, seller.clone()));
+        }
+        storage.remove(&DataKey::Role(Role::Settler, treasury.clone()));
+        write_legacy_offer(&e, 1000000);
+    });
+    assert_eq!(vault.get_schema_version(), 1);
+    assert!(!vault.has_role(&Role::Admin, &seller));
+
+    assert_eq!(vault.try_migrate(&treasury), Err(Ok(Error::Unauthorized)));
+
+    vault.migrate(&seller);
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION);
+    assert!(vault.has_role(&Role::Admin, &seller));
+    assert!(vault.has_role(&Role::PriceUpdater, &seller));
+    assert!(vault.has_role(&Role::Pauser, &seller));
+    assert!(vault.has_role(&Role::Sweeper, &seller));
+    assert!(vault.has_role(&Role::Settler, &treasury));
+    assert_eq!(vault.try_migrate(&seller), Err(Ok(Error::AlreadyMigrated)));
+
+    // requests survive the migration and settle as before
+    let request = vault.get_request(&buyer, &buy_token_client.address, &1);
+    assert_eq!(request.shares_amount, 5);
+    assert_eq!(request.epoch_id, 1);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 5);
+
+    vault.setle_epoch(&treasury);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 85);
+    assert_eq!(sell_token_client.balance(&seller), 5);
+}
+
+#[test]
+fn test_pause() {
+    let operations = [
+        Operation::Deposit,
+        Operation::RedeemRequest,
+        Operation::Claim,
+        Operation::Settlement,
+    ];
+    for paused in operations {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let token_admin = Address::generate(&e);
+        let seller = Address::generate(&e);
+        let treasury = Address::generate(&e);
+        let buyer = Address::generate(&e);
+        let other_buyer = Address::generate(&e);
+        let epoch_buyer = Address::generate(&e);
+        let client = Address::generate(&e);
+        let custodian = Address::generate(&e);
+        let queued_buyer = Address::generate(&e);
+
+        let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+        let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+        let vault = create_vault_contract(
+            &e,
+            &seller,
+            &treasury,
+            &sell_token_client.address,
+            &buy_token_client.address,
+            &price(1000000),
+        );
+
+        sell_token_admin.mint(&vault.address, &1000);
+        buy_token_admin.mint(&buyer, &1000);
+        buy_token_admin.mint(&other_buyer, &1000);
+        buy_token_admin.mint(&epoch_buyer, &1000);
+        buy_token_admin.mint(&client, &1000);
+        buy_token_admin.mint(&queued_buyer, &1000);
+        vault.deposit(&buyer, &buy_token_client.address, &100_i128, &100_i128);
+        vault.deposit(
+            &other_buyer,
+            &buy_token_client.address,
+            &100_i128,
+            &100_i128,
+        );
+        vault.deposit(
+            &epoch_buyer,
+            &buy_token_client.address,
+            &100_i128,
+            &100_i128,
+        );
+        vault.redeem_request(&buyer, &buy_token_client.address, &10_i128);
+        vault.redeem_request(&epoch_buyer, &buy_token_client.address, &10_i128);
+        vault.deposit(
+            &queued_buyer,
+            &buy_token_client.address,
+            &100_i128,
+            &100_i128,
+        );
+        vault.redeem_request(&queued_buyer, &buy_token_client.address, &10_i128);
+        vault.deposit(&client, &buy_token_client.address, &100_i128, &100_i128);
+        vault.approve_operator(
+            &client,
+            &custodian,
+            &vec![&e, OperatorScope::Redeem, OperatorScope::Claim],
+            &1000,
+        );
+        sell_token_client.approve(&client, &vault.address, &20, &1000);
+        vault.redeem_request_for(&custodian, &client, &buy_token_client.address, &10_i128);
+        vault.deposit_request(&buyer, &10_i128);
+        vault.setle_epoch(&treasury);
+
+        assert_eq!(
+            vault.try_pause(&treasury, &paused),
+            Err(Ok(Error::Unauthorized))
+        );
+        vault.pause(&seller, &paused);
+        for operation in operations {
+            assert_eq!(vault.is_paused(&operation), operation == paused);
+        }
+
+        let expected = |operation: Operation| {
+            if operation == paused {
+                Err(Ok(Error::Paused))
+            } else {
+                Ok(Ok(()))
+            }
+        };
+        assert_eq!(
+            vault.try_deposit(&buyer, &buy_token_client.address, &10_i128, &10_i128),
+            expected(Operation::Deposit)
+        );
+        assert_eq!(
+            vault.try_deposit_request(&other_buyer, &10_i128),
+            expected(Operation::Deposit)
+        );
+        assert_eq!(
+            vault.try_claim_request(&buyer, &buy_token_client.address),
+            expected(Operation::Claim)
+        );
+        assert_eq!(vault.try_claim_deposit(&buyer), expected(Operation::Claim));
+        assert_eq!(
+            vault.try_claim_epoch_request(&epoch_buyer, &buy_token_client.address, &1),
+            expected(Operation::Claim)
+        );
+        assert_eq!(
+            vault.try_claim_request_for(&custodian, &client, &buy_token_client.address, &client),
+            expected(Operation::Claim)
+        );
+        assert_eq!(
+            vault.try_redeem_request_for(&custodian, &client, &buy_token_client.address, &10_i128),
+            expected(Operation::RedeemRequest)
+        );
+        let processed = vault.try_process_claims(
+            &treasury,
+            &buy_token_client.address,
+            &vec![&e, queued_buyer.clone()],
+        );
+        if paused == Operation::Claim {
+            assert_eq!(processed, Err(Ok(Error::Paused)));
+        } else {
+            assert_eq!(processed, Ok(Ok(vec![&e])));
+        }
+        assert_eq!(
+            vault.try_redeem_request(&other_buyer, &buy_token_client.address, &10_i128),
+            expected(Operation::RedeemRequest)
+        );
+        if paused != Operation::RedeemRequest {
+            // cancelling is never paused so investors can always get their shares back
+            vault.cancel_request(&other_buyer);
+            vault.redeem_request(&other_buyer, &buy_token_client.address, &10_i128);
+        }
+        assert_eq!(
+            vault.try_setle_epoch(&treasury),
+            expected(Operation::Settlement)
+        );
+
+        vault.unpause(&seller, &paused);
+        assert!(!vault.is_paused(&paused));
+        match paused {
+            Operation::Deposit => {
+                vault.deposit(&buyer, &buy_token_client.address, &10_i128, &10_i128)
+            }
+            Operation::RedeemRequest => {
+                vault.redeem_request(&other_buyer, &buy_token_client.address, &10_i128)
+            }
+            Operation::Claim => vault.claim_request(&buyer, &buy_token_client.address),
+            Operation::Settlement => vault.setle_epoch(&treasury),
+        }
+    }
+}
+
+#[test]
+fn test_storage_ttl() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+
+    sell_token_admin.mint(&vault.address, &100);
+    buy_token_admin.mint(&buyer, &100);
+    vault.deposit(&buyer, &buy_token_client.address, &20_i128, &20_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
+    vault.setle_epoch(&treasury);
+
+    let request_key = DataKey::EpochRequest(buyer.clone(), 1);
+    let epochs_key = DataKey::RequestEpochs(buyer.clone());
+    let rate_key = DataKey::RedeemRate(1);
+    e.as_contract(&vault.address, || {
+        assert!(!e.storage().instance().has(&request_key));
+        assert!(!e.storage().instance().has(&rate_key));
+        assert_eq!(
+            e.storage().persistent().get_ttl(&request_key),
+            PERSISTENT_BUMP_AMOUNT
+        );
+        assert_eq!(
+            e.storage().persistent().get_ttl(&rate_key),
+            PERSISTENT_BUMP_AMOUNT
+        );
+        assert_eq!(e.storage().instance().get_ttl(), INSTANCE_BUMP_AMOUNT);
+    });
+
+    e.ledger()
+        .set_sequence_number(e.ledger().sequence() + 2 * DAY_IN_LEDGERS);
+    e.as_contract(&vault.address, || {
+        assert_eq!(
+            e.storage().persistent().get_ttl(&request_key),
+            PERSISTENT_BUMP_AMOUNT - 2 * DAY_IN_LEDGERS
+        );
+    });
+
+    vault.extend_ttl(&vec![&e, buyer.clone()], &vec![&e, 1_u32]);
+    e.as_contract(&vault.address, || {
+        assert_eq!(
+            e.storage().persistent().get_ttl(&request_key),
+            PERSISTENT_BUMP_AMOUNT
+        );
+        assert_eq!(
+            e.storage().persistent().get_ttl(&epochs_key),
+            PERSISTENT_BUMP_AMOUNT
+        );
+        assert_eq!(
+            e.storage().persistent().get_ttl(&rate_key),
+            PERSISTENT_BUMP_AMOUNT
+        );
+        assert_eq!(e.storage().instance().get_ttl(), INSTANCE_BUMP_AMOUNT);
+    });
+
+    // claiming removes the request entry instead of keeping an empty one alive
+    vault.claim_request(&buyer, &buy_token_client.address);
+    e.as_contract(&vault.address, || {
+        assert!(!e.storage().persistent().has(&request_key));
+        assert!(!e.storage().persistent().has(&epochs_key));
+    });
+}
+
+#[test]
+fn test_request_cost_is_flat() {
+    // ledger entries and bytes touched by a new user's redeem_request after `holders`
+    // other users requested
+    fn redeem_request_footprint(holders: u32) -> (u32, u32, u32, u32) {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let token_admin = Address::generate(&e);
+        let seller = Address::generate(&e);
+        let treasury = Address::generate(&e);
+
+        let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+        let (buy_token_client, _) = create_token_contract(&e, &token_admin);
+
+        let vault = create_vault_contract(
+            &e,
+            &seller,
+            &treasury,
+            &sell_token_client.address,
+            &buy_token_client.address,
+            &price(1000000),
+        );
+
+        for _ in 0..holders {
+            let holder = Address::generate(&e);
+            sell_token_admin.mint(&holder, &10);
+            vault.redeem_request(&holder, &buy_token_client.address, &10_i128);
+        }
+
+        let user = Address::generate(&e);
+        sell_token_admin.mint(&user, &10);
+        vault.redeem_request(&user, &buy_token_client.address, &10_i128);
+        let resources = e.cost_estimate().resources();
+        (
+            resources.read_entries,
+            resources.write_entries,
+            resources.read_bytes,
+            resources.write_bytes,
+        )
+    }
+
+    assert_eq!(redeem_request_footprint(1), redeem_request_footprint(100));
+}
+
+#[test]
+fn test_migrate_instance_requests() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+
+    let other_buyer = Address::generate(&e);
+    sell_token_admin.mint(&vault.address, &100);
+    buy_token_admin.mint(&buyer, &100);
+    buy_token_admin.mint(&other_buyer, &100);
+    vault.deposit(&buyer, &buy_token_client.address, &20_i128, &20_i128);
+    vault.deposit(&other_buyer, &buy_token_client.address, &20_i128, &20_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
+    vault.redeem_request(&other_buyer, &buy_token_client.address, &7_i128);
+    vault.setle_epoch(&treasury);
+
+    // rewrite the storage as a version 2 vault, which kept a single request per sender and
+    // the rates in the instance. A request written by version 3 stays in persistent storage.
+    let request_key = DataKey::Request(buyer.clone());
+    let other_request_key = DataKey::Request(other_buyer.clone());
+    let rate_key = DataKey::RedeemRate(1);
+    e.as_contract(&vault.address, || {
+        for (sender, key) in [(&buyer, &request_key), (&other_buyer, &other_request_key)] {
+            let epoch_key = DataKey::EpochRequest(sender.clone(), 1);
+            let request: RedeemRequest = e.storage().persistent().get(&epoch_key).unwrap();
+            e.storage().persistent().remove(&epoch_key);
+            e.storage()
+                .persistent()
+                .remove(&DataKey::RequestEpochs(sender.clone()));
+            if sender == &buyer {
+                e.storage().instance().set(key, &request);
+            } else {
+                e.storage().persistent().set(key, &request);
+            }
+        }
+        e.storage().persistent().remove(&rate_key);
+        e.storage().instance().set(&rate_key, &1000000_u32);
+        e.storage().persistent().remove(&DataKey::EpochDeposit(1));
+        e.storage().persistent().remove(&DataKey::EpochFill(1));
+        e.storage().instance().set(&DataKey::SchemaVersion, &2_u32);
+        write_legacy_offer(&e, 1000000);
+    });
+
+    vault.migrate(&seller);
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(
+        vault.get_redeem_rate(&buy_token_client.address, &1),
+        price(1000000)
+    );
+    e.as_contract(&vault.address, || {
+        assert!(!e.storage().instance().has(&rate_key));
+        assert!(e.storage().persistent().has(&rate_key));
+    });
+
+    // views decode single requests without moving them
+    let request = vault.get_request(&buyer, &buy_token_client.address, &1);
+    assert_eq!(request.shares_amount, 5);
+    assert_eq!(request.epoch_id, 1);
+    assert_eq!(
+        vault.preview_claim(&buyer, &buy_token_client.address),
+        (5, 5, 0)
+    );
+    e.as_contract(&vault.address, || {
+        assert!(e.storage().instance().has(&request_key));
+        assert!(!e
+            .storage()
+            .persistent()
+            .has(&DataKey::EpochRequest(buyer.clone(), 1)));
+    });
+
+    // they are moved to their epoch when written, here by the claim
+    vault.claim_request(&buyer, &buy_token_client.address);
+    vault.claim_request(&other_buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 85);
+    assert_eq!(buy_token_client.balance(&other_buyer), 87);
+    e.as_contract(&vault.address, || {
+        assert!(!e.storage().instance().has(&request_key));
+        assert!(!e.storage().persistent().has(&other_request_key));
+    });
+}
+
+#[test]
+#[cfg(not(feature = "legacy-initialize"))]
+fn test_constructor_admin() {
+    let e = Env::default();
+
+    let token_admin = Address::generate(&e);
+    let admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let (sell_token_client, _) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, _) = create_token_contract(&e, &token_admin);
+
+    let vault = VaultClient::new(
+        &e,
+        &e.register(
+            crate::vault::Vault,
+            (
+                &admin,
+                &seller,
+                &treasury,
+                sell_token_client.address.clone(),
+                buy_token_client.address.clone(),
+                price(1000000),
+            ),
+        ),
+    );
+
+    assert!(vault.has_role(&Role::Admin, &admin));
+    assert!(!vault.has_role(&Role::Admin, &seller));
+    assert!(vault.has_role(&Role::PriceUpdater, &seller));
+    assert!(vault.has_role(&Role::Pauser, &seller));
+    assert!(vault.has_role(&Role::Sweeper, &seller));
+    assert!(vault.has_role(&Role::Settler, &treasury));
+    assert_eq!(vault.get_offer().seller, seller);
+}
+
+#[test]
+#[cfg(not(feature = "legacy-initialize"))]
+#[should_panic(expected = "Error(Contract, #4)")]
+fn test_constructor_zero_price() {
+    let e = Env::default();
+
+    let token_admin = Address::generate(&e);
+    let (sell_token_client, _) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, _) = create_token_contract(&e, &token_admin);
+
+    create_vault_contract(
+        &e,
+        &Address::generate(&e),
+        &Address::generate(&e),
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(0),
+    );
+}
+
+#[contract]
+struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn set_price(e: Env, price: i128, timestamp: u64) {
+        e.storage()
+            .instance()
+            .set(&symbol_short!("price"), &PriceData { price, timestamp });
+    }
+
+    pub fn decimals(_e: Env) -> u32 {
+        14
+    }
+
+    pub fn lastprice(e: Env, _asset: Asset) -> Option<PriceData> {
+        e.storage().instance().get(&symbol_short!("price"))
+    }
+}
+
+#[test]
+fn test_oracle_price() {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().set_timestamp(1_000_000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &10000);
+    buy_token_admin.mint(&buyer, &10000);
+
+    let oracle = MockOracleClient::new(&e, &e.register(MockOracle, ()));
+    let asset = Asset::Stellar(sell_token_client.address.clone());
+    assert_eq!(
+        vault.try_set_oracle(&seller, &oracle.address, &asset, &3600, &500, &false),
+        Ok(Ok(()))
+    );
+    assert_eq!(
+        vault.try_set_oracle(&treasury, &oracle.address, &asset, &3600, &500, &false),
+        Err(Ok(Error::Unauthorized))
+    );
+    let config = vault.get_oracle().unwrap();
+    assert_eq!(config.oracle, oracle.address);
+    assert_eq!(config.asset, asset);
+    assert_eq!(config.max_age, 3600);
+    assert_eq!(config.max_deviation_bps, 500);
+    assert!(!config.allow_fallback);
+
+    // no quote yet
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &100_i128, &0_i128),
+        Err(Ok(Error::StalePrice))
+    );
+
+    // 1.04 with 14 decimals, within 5% of the stored price
+    oracle.set_price(&104_000_000_000_000, &999_000);
+    assert_eq!(
+        vault.get_price(&buy_token_client.address),
+        Price {
+            mantissa: 104_000_000_000_000,
+            decimals: 14
+        }
+    );
+    vault.deposit(&buyer, &buy_token_client.address, &100_i128, &96_i128);
+    assert_eq!(sell_token_client.balance(&buyer), 96);
+
+    vault.redeem_request(&buyer, &buy_token_client.address, &50_i128);
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vault.get_redeem_rate(&buy_token_client.address, &1),
+        vault.get_price(&buy_token_client.address)
+    );
+    assert_eq!(buy_token_client.balance(&vault.address), 52);
+
+    // stale quote
+    e.ledger().set_timestamp(999_000 + 3601);
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &100_i128, &0_i128),
+        Err(Ok(Error::StalePrice))
+    );
+    assert_eq!(vault.try_setle_epoch(&treasury), Err(Ok(Error::StalePrice)));
+
+    // out-of-band quote
+    oracle.set_price(&106_000_000_000_000, &e.ledger().timestamp());
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &100_i128, &0_i128),
+        Err(Ok(Error::PriceOutOfBounds))
+    );
+
+    // with fallback enabled bad quotes use the stored price
+    vault.set_oracle(&seller, &oracle.address, &asset, &3600, &500, &true);
+    assert_eq!(vault.get_price(&buy_token_client.address), price(1000000));
+    vault.deposit(&buyer, &buy_token_client.address, &100_i128, &100_i128);
+    assert_eq!(sell_token_client.balance(&buyer), 146);
+
+    oracle.set_price(&95_000_000_000_000, &e.ledger().timestamp());
+    assert_eq!(
+        vault.get_price(&buy_token_client.address),
+        Price {
+            mantissa: 95_000_000_000_000,
+            decimals: 14
+        }
+    );
+
+    vault.remove_oracle(&seller);
+    assert!(vault.get_oracle().is_none());
+    assert_eq!(vault.get_price(&buy_token_client.address), price(1000000));
+}
+
+#[test]
+fn test_fees() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+    let fee_recipient = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &10000);
+    buy_token_admin.mint(&buyer, &10000);
+
+    let fees = vault.get_fees();
+    assert_eq!(fees.entry_fee_bps, 0);
+    assert_eq!(fees.exit_fee_bps, 0);
+    assert_eq!(fees.recipient, treasury);
+
+    assert_eq!(
+        vault.try_set_fees(&treasury, &100, &200),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_set_fees(&seller, &1001, &200),
+        Err(Ok(Error::FeeTooHigh))
+    );
+    assert_eq!(
+        vault.try_set_fees(&seller, &100, &1001),
+        Err(Ok(Error::FeeTooHigh))
+    );
+    vault.set_fees(&seller, &100, &200);
+    vault.set_fee_recipient(&seller, &fee_recipient);
+    let fees = vault.get_fees();
+    assert_eq!(fees.entry_fee_bps, 100);
+    assert_eq!(fees.exit_fee_bps, 200);
+    assert_eq!(fees.recipient, fee_recipient);
+    assert_eq!(vault.preview_fees(&1000), (10, 20));
+
+    vault.deposit(&buyer, &buy_token_client.address, &1000_i128, &990_i128);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_deposit"),
+                    &buyer,
+                    &treasury,
+                    &buy_token_client.address
+                )
+                    .into_val(&e),
+                (1000_i128, 990_i128, 10_i128).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(sell_token_client.balance(&buyer), 990);
+    assert_eq!(buy_token_client.balance(&treasury), 990);
+    assert_eq!(buy_token_client.balance(&fee_recipient), 10);
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+
+    vault.redeem_request(&buyer, &buy_token_client.address, &500_i128);
+    vault.setle_epoch(&treasury);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_claim_request"),
+                    &buyer,
+                    &buy_token_client.address
+                )
+                    .into_val(&e),
+                (1_u32, 500_i128, 490_i128, 10_i128).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(buy_token_client.balance(&buyer), 9000 + 490);
+    assert_eq!(buy_token_client.balance(&fee_recipient), 20);
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+}
+
+#[test]
+fn test_epoch_schedule() {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().set_timestamp(1000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let early = Address::generate(&e);
+    let late = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&early, &1000);
+    sell_token_admin.mint(&late, &1000);
+    buy_token_admin.mint(&treasury, &10000);
+
+    // without a schedule requests always go to the current epoch
+    assert_eq!(vault.get_schedule(), None);
+    assert_eq!(vault.get_epoch_window(&1), None);
+
+    assert_eq!(
+        vault.try_set_schedule(&treasury, &1000, &100, &50, &80),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_set_schedule(&seller, &1000, &0, &50, &80),
+        Err(Ok(Error::InvalidSchedule))
+    );
+    assert_eq!(
+        vault.try_set_schedule(&seller, &1000, &100, &90, &80),
+        Err(Ok(Error::InvalidSchedule))
+    );
+    vault.set_schedule(&seller, &1000, &100, &50, &80);
+    assert_eq!(
+        vault.get_schedule(),
+        Some(EpochSchedule {
+            period: 100,
+            cutoff_offset: 50,
+            settlement_offset: 80,
+        })
+    );
+    assert_eq!(
+        vault.get_epoch_window(&1),
+        Some(EpochWindow {
+            start: 1000,
+            cutoff: 1050,
+            settle_after: 1080,
+        })
+    );
+
+    // before the cutoff the request belongs to the current epoch
+    e.ledger().set_timestamp(1050);
+    vault.redeem_request(&early, &buy_token_client.address, &100);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_redeem_request"),
+                    &early,
+                    &buy_token_client.address
+                )
+                    .into_val(&e),
+                (100_i128, 100_i128, 100_i128, 1_u32).into_val(&e)
+            )
+        ]
+    );
+
+    // after the cutoff it rolls into the next epoch
+    e.ledger().set_timestamp(1051);
+    vault.redeem_request(&late, &buy_token_client.address, &200);
+    assert_eq!(
+        vault
+            .get_request(&late, &buy_token_client.address, &2)
+            .shares_amount,
+        200
+    );
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 100);
+    assert_eq!(vault.get_next_total_redeem(&buy_token_client.address), 200);
+    vault.redeem_request(&early, &buy_token_client.address, &10);
+    assert_eq!(
+        vault
+            .get_requests(&early, &buy_token_client.address, &1, &2)
+            .len(),
+        2
+    );
+    assert_eq!(vault.get_next_total_redeem(&buy_token_client.address), 210);
+
+    assert_eq!(
+        vault.try_setle_epoch(&treasury),
+        Err(Ok(Error::SettlementTooEarly))
+    );
+    e.ledger().set_timestamp(1080);
+    vault.setle_epoch(&treasury);
+    assert_eq!(vault.get_epoch_id(), 2);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 210);
+    assert_eq!(vault.get_next_total_redeem(&buy_token_client.address), 0);
+    assert_eq!(
+        vault.get_epoch_window(&2),
+        Some(EpochWindow {
+            start: 1100,
+            cutoff: 1150,
+            settle_after: 1180,
+        })
+    );
+
+    // settled requests can only be claimed
+    assert_eq!(
+        vault.try_cancel_epoch_request(&early, &buy_token_client.address, &1),
+        Err(Ok(Error::InvalidEpochId))
+    );
+    vault.claim_request(&early, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&early), 100);
+
+    // a late settlement starts the next epoch at the settlement time
+    vault.cancel_request(&early);
+    vault.cancel_request(&late);
+    assert_eq!(sell_token_client.balance(&early), 900);
+    assert_eq!(sell_token_client.balance(&late), 1000);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 0);
+    e.ledger().set_timestamp(1500);
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vault.get_epoch_window(&3),
+        Some(EpochWindow {
+            start: 1500,
+            cutoff: 1550,
+            settle_after: 1580,
+        })
+    );
+}
+
+#[test]
+fn test_partial_settlement() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let user1 = Address::generate(&e);
+    let user2 = Address::generate(&e);
+    let user3 = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    for user in [&user1, &user2, &user3] {
+        sell_token_admin.mint(user, &100);
+        vault.redeem_request(user, &buy_token_client.address, &100);
+    }
+
+    // the treasury can only fund a third of the epoch
+    buy_token_admin.mint(&treasury, &100);
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_setle_epoch"), &treasury).into_val(&e),
+                (
+                    1_u32,
+                    300_i128,
+                    100_i128,
+                    100_i128,
+                    price(1000000),
+                    333333_u32
+                )
+                    .into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(
+        vault.get_epoch_fill(&buy_token_client.address, &1),
+        Some(EpochFill {
+            fill_ratio: 333333,
+            shares_left: 300,
+            filled_left: 100,
+            assets_left: 100,
+        })
+    );
+    assert_eq!(sell_token_client.balance(&seller), 100);
+    assert_eq!(buy_token_client.balance(&treasury), 0);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 200);
+
+    // the unfilled shares wait in the next epoch
+    vault.claim_request(&user1, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&user1), 33);
+    let request = vault.get_request(&user1, &buy_token_client.address, &2);
+    assert_eq!(request.shares_amount, 67);
+    assert_eq!(request.epoch_id, 2);
+
+    buy_token_admin.mint(&treasury, &1000);
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vault
+            .get_epoch_fill(&buy_token_client.address, &2)
+            .unwrap()
+            .fill_ratio,
+        1000000
+    );
+    assert_eq!(sell_token_client.balance(&seller), 300);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 0);
+
+    // late claims go through every epoch the shares were carried over to
+    vault.claim_request(&user2, &buy_token_client.address);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_claim_request"),
+                    &user2,
+                    &buy_token_client.address
+                )
+                    .into_val(&e),
+                (1_u32, 100_i128, 100_i128, 0_i128).into_val(&e)
+            )
+        ]
+    );
+    vault.claim_request(&user3, &buy_token_client.address);
+    vault.claim_request(&user1, &buy_token_client.address);
+    for user in [&user1, &user2, &user3] {
+        assert_eq!(buy_token_client.balance(user), 100);
+        assert!(vault
+            .get_requests(user, &buy_token_client.address, &1, &2)
+            .is_empty());
+    }
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+    assert_eq!(
+        vault.get_epoch_fill(&buy_token_client.address, &1),
+        Some(EpochFill {
+            fill_ratio: 333333,
+            shares_left: 0,
+            filled_left: 0,
+            assets_left: 0,
+        })
+    );
+}
+
+#[test]
+fn test_deposit_request() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let fee_recipient = Address::generate(&e);
+    let user1 = Address::generate(&e);
+    let user2 = Address::generate(&e);
+    let user3 = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &10000);
+    for user in [&user1, &user2, &user3] {
+        buy_token_admin.mint(user, &2000);
+    }
+    vault.set_fees(&seller, &100, &0);
+    vault.set_fee_recipient(&seller, &fee_recipient);
+
+    vault.deposit_request(&user1, &600);
+    vault.deposit_request(&user1, &400);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_deposit_request"), &user1).into_val(&e),
+                (400_i128, 1000_i128, 1000_i128, 1_u32).into_val(&e)
+            )
+        ]
+    );
+    vault.deposit_request(&user2, &500);
+    vault.deposit_request(&user3, &300);
+    assert_eq!(vault.get_total_deposit(), 1800);
+    assert_eq!(buy_token_client.balance(&vault.address), 1800);
+
+    vault.cancel_deposit(&user3);
+    assert_eq!(buy_token_client.balance(&user3), 2000);
+    assert_eq!(vault.get_total_deposit(), 1500);
+    assert_eq!(
+        vault.try_cancel_deposit(&user3),
+        Err(Ok(Error::NoDepositRequest))
+    );
+    assert_eq!(
+        vault.try_claim_deposit(&user1),
+        Err(Ok(Error::EpochNotSetled))
+    );
+
+    // the deposits are priced when the epoch is settled
+    vault.updt_price(&seller, &price(2000000));
+    vault.setle_epoch(&treasury);
+    assert_eq!(vault.get_deposit_rate(&1), price(2000000));
+    assert_eq!(
+        vault.get_epoch_deposit(&1),
+        Some(EpochDeposit {
+            rate: price(2000000),
+            assets_left: 1500,
+            shares_left: 742,
+        })
+    );
+    assert_eq!(vault.get_total_deposit(), 0);
+    assert_eq!(buy_token_client.balance(&treasury), 1485);
+    assert_eq!(buy_token_client.balance(&fee_recipient), 15);
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+
+    assert_eq!(
+        vault.try_cancel_deposit(&user1),
+        Err(Ok(Error::InvalidEpochId))
+    );
+    vault.claim_deposit(&user1);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_claim_deposit"), &user1).into_val(&e),
+                (1000_i128, 494_i128).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(vault.get_deposit_request(&user1).assets_amount, 0);
+
+    // a new request claims the settled one first, the last claim takes the rounding dust
+    vault.deposit_request(&user2, &100);
+    assert_eq!(sell_token_client.balance(&user2), 248);
+    let request = vault.get_deposit_request(&user2);
+    assert_eq!(request.assets_amount, 100);
+    assert_eq!(request.epoch_id, 2);
+    assert_eq!(sell_token_client.balance(&vault.address), 10000 - 742);
+    assert_eq!(vault.get_epoch_deposit(&1).unwrap().shares_left, 0);
+}
+
+#[test]
+fn test_request_history() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&buyer, &1000);
+    buy_token_admin.mint(&treasury, &10000);
+
+    // a request per epoch, without claiming the previous ones
+    for (epoch_id, amount) in [(1_u32, 10_i128), (2, 20), (3, 30)] {
+        vault.redeem_request(&buyer, &buy_token_client.address, &amount);
+        assert_eq!(
+            vault
+                .get_request(&buyer, &buy_token_client.address, &epoch_id)
+                .shares_amount,
+            amount
+        );
+        vault.updt_price(&seller, &price(1000000 * epoch_id as i128));
+        vault.setle_epoch(&treasury);
+    }
+    vault.redeem_request(&buyer, &buy_token_client.address, &40);
+
+    let requests = vault.get_requests(&buyer, &buy_token_client.address, &0, &10);
+    assert_eq!(requests.len(), 4);
+    let requests = vault.get_requests(&buyer, &buy_token_client.address, &2, &3);
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests.get(0).unwrap().epoch_id, 2);
+    assert_eq!(requests.get(0).unwrap().shares_amount, 20);
+    assert_eq!(requests.get(1).unwrap().epoch_id, 3);
+    assert_eq!(requests.get(1).unwrap().shares_amount, 30);
+
+    // claim one epoch on its own
+    assert_eq!(
+        vault.try_claim_epoch_request(&buyer, &buy_token_client.address, &4),
+        Err(Ok(Error::EpochNotSetled))
+    );
+    vault.claim_epoch_request(&buyer, &buy_token_client.address, &2);
+    assert_eq!(buy_token_client.balance(&buyer), 40);
+    assert_eq!(
+        vault
+            .get_request(&buyer, &buy_token_client.address, &2)
+            .shares_amount,
+        0
+    );
+    assert_eq!(
+        vault.try_claim_epoch_request(&buyer, &buy_token_client.address, &2),
+        Err(Ok(Error::NoRedeemRequest))
+    );
+
+    // then the remaining settled ones at once, the pending one is left alone
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 40 + 10 + 90);
+    let requests = vault.get_requests(&buyer, &buy_token_client.address, &0, &10);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests.get(0).unwrap().epoch_id, 4);
+    assert_eq!(
+        vault.try_claim_request(&buyer, &buy_token_client.address),
+        Err(Ok(Error::NoRedeemRequest))
+    );
+
+    assert_eq!(
+        vault.try_cancel_epoch_request(&buyer, &buy_token_client.address, &3),
+        Err(Ok(Error::NoRedeemRequest))
+    );
+    vault.cancel_epoch_request(&buyer, &buy_token_client.address, &4);
+    assert_eq!(sell_token_client.balance(&buyer), 1000 - 60);
+    assert!(vault
+        .get_requests(&buyer, &buy_token_client.address, &0, &10)
+        .is_empty());
+}
+
+#[test]
+fn test_share_token() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+    let other_buyer = Address::generate(&e);
+    let spender = Address::generate(&e);
+
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    buy_token_admin.mint(&buyer, &1000);
+    buy_token_admin.mint(&other_buyer, &1000);
+
+    // the vault is its own sell token
+    let vault_address = Address::generate(&e);
+    #[cfg(not(feature = "legacy-initialize"))]
+    e.register_at(
+        &vault_address,
+        crate::vault::Vault,
+        (
+            &seller,
+            &seller,
+            &treasury,
+            vault_address.clone(),
+            buy_token_client.address.clone(),
+            price(1000000),
+        ),
+    );
+    #[cfg(feature = "legacy-initialize")]
+    e.register_at(&vault_address, crate::vault::Vault, ());
+    let vault = VaultClient::new(&e, &vault_address);
+    #[cfg(feature = "legacy-initialize")]
+    vault.initialize(
+        &seller,
+        &treasury,
+        &vault_address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    let shares = token::Client::new(&e, &vault_address);
+
+    assert_eq!(
+        vault.try_set_share_metadata(
+            &treasury,
+            &7,
+            &String::from_str(&e, "Vault Share"),
+            &String::from_str(&e, "VSH")
+        ),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.set_share_metadata(
+        &seller,
+        &7,
+        &String::from_str(&e, "Vault Share"),
+        &String::from_str(&e, "VSH"),
+    );
+    assert_eq!(shares.decimals(), 7);
+    assert_eq!(shares.name(), String::from_str(&e, "Vault Share"));
+    assert_eq!(shares.symbol(), String::from_str(&e, "VSH"));
+
+    // shares are minted on deposit
+    vault.deposit(&buyer, &buy_token_client.address, &1000, &1000);
+    assert_eq!(shares.balance(&buyer), 1000);
+    assert_eq!(vault.total_supply(), 1000);
+    assert_eq!(
+        vault.try_set_share_metadata(
+            &seller,
+            &6,
+            &String::from_str(&e, "Vault Share"),
+            &String::from_str(&e, "VSH")
+        ),
+        Err(Ok(Error::SharesAlreadyIssued))
+    );
+
+    shares.transfer(&buyer, &other_buyer, &100);
+    shares.approve(&buyer, &spender, &200, &(e.ledger().sequence() + 100));
+    assert_eq!(shares.allowance(&buyer, &spender), 200);
+    shares.transfer_from(&spender, &buyer, &other_buyer, &150);
+    assert_eq!(shares.allowance(&buyer, &spender), 50);
+    assert_eq!(
+        shares.try_transfer_from(&spender, &buyer, &other_buyer, &51),
+        Err(Ok(Error::InsufficientAllowance.into()))
+    );
+    shares.burn_from(&spender, &buyer, &50);
+    assert_eq!(
+        shares.try_transfer(&buyer, &other_buyer, &701),
+        Err(Ok(Error::InsufficientBalance.into()))
+    );
+    assert_eq!(shares.balance(&buyer), 700);
+    assert_eq!(shares.balance(&other_buyer), 250);
+    assert_eq!(vault.total_supply(), 950);
+
+    // requested shares are escrowed in the vault and burned at settlement
+    vault.redeem_request(&buyer, &buy_token_client.address, &400);
+    vault.deposit_request(&other_buyer, &200);
+    assert_eq!(shares.balance(&buyer), 300);
+    assert_eq!(shares.balance(&vault_address), 400);
+    vault.setle_epoch(&treasury);
+    assert_eq!(shares.balance(&vault_address), 0);
+    assert_eq!(shares.balance(&seller), 0);
+    assert_eq!(vault.total_supply(), 550);
+
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 400);
+    vault.claim_deposit(&other_buyer);
+    assert_eq!(shares.balance(&other_buyer), 450);
+    assert_eq!(
+        vault.total_supply(),
+        shares.balance(&buyer) + shares.balance(&other_buyer)
+    );
+}
+
+#[contract]
+struct MockRegistry;
+
+#[contractimpl]
+impl MockRegistry {
+    pub fn verify(e: Env, account: Address) {
+        e.storage().instance().set(&account, &true);
+    }
+
+    pub fn is_verified(e: Env, account: Address) -> bool {
+        e.storage().instance().has(&account)
+    }
+}
+
+#[test]
+fn test_compliance() {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().set_timestamp(1000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let officer = Address::generate(&e);
+    let investor = Address::generate(&e);
+    let verified = Address::generate(&e);
+    let sanctioned = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &10000);
+    for account in [&investor, &verified, &sanctioned] {
+        buy_token_admin.mint(account, &1000);
+    }
+
+    // nothing is enforced until configured, apart from the denylist
+    assert_eq!(
+        vault.get_compliance(),
+        ComplianceConfig {
+            enforced: false,
+            registry: None,
+        }
+    );
+    vault.deposit(&sanctioned, &buy_token_client.address, &100, &100);
+
+    let registry = MockRegistryClient::new(&e, &e.register(MockRegistry, ()));
+    vault.set_compliance(&seller, &true, &Some(registry.address.clone()));
+    assert_eq!(
+        vault.try_allow(&seller, &investor, &0),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.grant_role(&seller, &Role::Compliance, &officer);
+
+    assert_eq!(
+        vault.try_deposit(&investor, &buy_token_client.address, &100, &100),
+        Err(Ok(Error::NotAllowlisted))
+    );
+    vault.allow(&officer, &investor, &2000);
+    assert_eq!(vault.get_allowed(&investor), Some(2000));
+    vault.deposit(&investor, &buy_token_client.address, &100, &100);
+
+    // accounts verified by the registry need no allowlist entry
+    registry.verify(&verified);
+    vault.deposit(&verified, &buy_token_client.address, &100, &100);
+
+    vault.redeem_request(&investor, &buy_token_client.address, &50);
+    vault.deposit_request(&investor, &100);
+
+    // removed investors keep their pending positions but cannot open new ones
+    vault.disallow(&officer, &investor);
+    assert_eq!(vault.get_allowed(&investor), None);
+    assert_eq!(
+        vault.try_redeem_request(&investor, &buy_token_client.address, &10),
+        Err(Ok(Error::NotAllowlisted))
+    );
+    vault.setle_epoch(&treasury);
+    vault.claim_request(&investor, &buy_token_client.address);
+    vault.claim_deposit(&investor);
+    assert_eq!(buy_token_client.balance(&investor), 850);
+    assert_eq!(sell_token_client.balance(&investor), 150);
+
+    vault.allow(&officer, &investor, &2000);
+    e.ledger().set_timestamp(2001);
+    assert_eq!(
+        vault.try_deposit_request(&investor, &100),
+        Err(Ok(Error::AllowlistExpired))
+    );
+
+    // sanctioned accounts cannot claim or cancel either
+    vault.allow(&officer, &sanctioned, &0);
+    vault.redeem_request(&sanctioned, &buy_token_client.address, &50);
+    vault.deny(&officer, &sanctioned);
+    assert!(vault.is_denied(&sanctioned));
+    assert_eq!(
+        vault.try_cancel_request(&sanctioned),
+        Err(Ok(Error::Denylisted))
+    );
+    assert_eq!(
+        vault.try_deposit(&sanctioned, &buy_token_client.address, &100, &100),
+        Err(Ok(Error::Denylisted))
+    );
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vault.try_claim_request(&sanctioned, &buy_token_client.address),
+        Err(Ok(Error::Denylisted))
+    );
+    vault.undeny(&officer, &sanctioned);
+    vault.claim_request(&sanctioned, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&sanctioned), 950);
+}
+
+#[test]
+fn test_limits() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &100000);
+    buy_token_admin.mint(&alice, &10000);
+    buy_token_admin.mint(&bob, &10000);
+
+    assert_eq!(
+        vault.get_limits(),
+        Limits {
+            min_deposit: 0,
+            max_deposit: 0,
+            min_redeem: 0,
+            max_redeem: 0,
+            user_deposit_cap: 0,
+            user_redeem_cap: 0,
+            epoch_redeem_cap: 0,
+            tvl_cap: 0,
+        }
+    );
+    assert_eq!(
+        vault.try_set_tvl_cap(&alice, &1000),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_set_deposit_limits(&seller, &200, &100),
+        Err(Ok(Error::InvalidLimits))
+    );
+    assert_eq!(
+        vault.try_set_user_caps(&seller, &-1, &0),
+        Err(Ok(Error::InvalidLimits))
+    );
+
+    // per-transaction limits
+    vault.set_deposit_limits(&seller, &100, &2000);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_deposit_limits_set"), &seller).into_val(&e),
+                (100_i128, 2000_i128).into_val(&e)
+            )
+        ]
+    );
+    vault.set_redeem_limits(&seller, &50, &1000);
+    assert_eq!(
+        vault.try_deposit(&alice, &buy_token_client.address, &99, &0),
+        Err(Ok(Error::DepositTooSmall))
+    );
+    assert_eq!(
+        vault.try_deposit_request(&alice, &2001),
+        Err(Ok(Error::DepositTooLarge))
+    );
+    vault.deposit(&alice, &buy_token_client.address, &2000, &0);
+    vault.deposit(&bob, &buy_token_client.address, &2000, &0);
+    assert_eq!(
+        vault.try_redeem_request(&alice, &buy_token_client.address, &49),
+        Err(Ok(Error::RedeemTooSmall))
+    );
+    assert_eq!(
+        vault.try_redeem_request(&alice, &buy_token_client.address, &1001),
+        Err(Ok(Error::RedeemTooLarge))
+    );
+
+    // per-user caps count deposits and requests net of cancellations
+    vault.set_user_caps(&seller, &3000, &800);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_user_caps_set"), &seller).into_val(&e),
+                (3000_i128, 800_i128).into_val(&e)
+            )
+        ]
+    );
+    vault.deposit_request(&alice, &1000);
+    assert_eq!(vault.get_user_deposited(&alice), 3000);
+    assert_eq!(
+        vault.try_deposit(&alice, &buy_token_client.address, &100, &0),
+        Err(Ok(Error::UserDepositCapExceeded))
+    );
+    vault.cancel_deposit(&alice);
+    assert_eq!(vault.get_user_deposited(&alice), 2000);
+    vault.deposit(&alice, &buy_token_client.address, &100, &0);
+
+    vault.redeem_request(&alice, &buy_token_client.address, &800);
+    assert_eq!(
+        vault.try_redeem_request(&alice, &buy_token_client.address, &50),
+        Err(Ok(Error::UserRedeemCapExceeded))
+    );
+    vault.cancel_request(&alice);
+    assert_eq!(vault.get_user_redeemed(&alice), 0);
+
+    // the per-epoch cap bounds the total requested in the epoch
+    vault.set_epoch_redeem_cap(&seller, &1200);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_epoch_redeem_cap_set"), &seller).into_val(&e),
+                1200_i128.into_val(&e)
+            )
+        ]
+    );
+    vault.redeem_request(&alice, &buy_token_client.address, &700);
+    assert_eq!(
+        vault.try_redeem_request(&bob, &buy_token_client.address, &600),
+        Err(Ok(Error::EpochRedeemCapExceeded))
+    );
+    vault.redeem_request(&bob, &buy_token_client.address, &500);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 1200);
+
+    // the TVL cap bounds the buy tokens deposited minus those paid out
+    assert_eq!(vault.get_total_assets(), 4100);
+    vault.set_tvl_cap(&seller, &4200);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_tvl_cap_set"), &seller).into_val(&e),
+                4200_i128.into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(
+        vault.try_deposit(&bob, &buy_token_client.address, &101, &0),
+        Err(Ok(Error::TvlCapExceeded))
+    );
+    vault.setle_epoch(&treasury);
+    assert_eq!(vault.get_total_assets(), 2900);
+    vault.deposit(&bob, &buy_token_client.address, &1000, &0);
+    assert_eq!(vault.get_total_assets(), 3900);
+    assert_eq!(vault.get_user_deposited(&bob), 3000);
+}
+
+#[test]
+fn test_previews() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let user1 = Address::generate(&e);
+    let user2 = Address::generate(&e);
+    let user3 = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1500000),
+    );
+    vault.set_fees(&seller, &100, &200);
+    sell_token_admin.mint(&vault.address, &100000);
+    for user in [&user1, &user2, &user3] {
+        buy_token_admin.mint(user, &10000);
+    }
+
+    // deposits are quoted with the entry fee and rounding of the real call
+    assert_eq!(
+        vault.preview_deposit(&buy_token_client.address, &1001),
+        (660, 11)
+    );
+    vault.deposit(&user1, &buy_token_client.address, &1001, &0);
+    assert_eq!(sell_token_client.balance(&user1), 660);
+    vault.set_deposit_limits(&seller, &100, &0);
+    assert_eq!(
+        vault.try_preview_deposit(&buy_token_client.address, &99),
+        Err(Ok(Error::DepositTooSmall))
+    );
+
+    for user in [&user2, &user3] {
+        sell_token_admin.mint(user, &500);
+    }
+    assert_eq!(
+        vault.preview_redeem(&buy_token_client.address, &333),
+        (489, 10)
+    );
+    vault.set_epoch_redeem_cap(&seller, &1500);
+    for user in [&user1, &user2, &user3] {
+        vault.redeem_request(user, &buy_token_client.address, &500);
+    }
+    assert_eq!(
+        vault.try_preview_redeem(&buy_token_client.address, &1),
+        Err(Ok(Error::EpochRedeemCapExceeded))
+    );
+    vault.deposit_request(&user2, &500);
+
+    // the treasury funds the settlement with its balance and the epoch's deposits
+    let preview = vault.preview_settlement(&buy_token_client.address);
+    assert_eq!(
+        preview,
+        SettlementPreview {
+            epoch_id: 1,
+            price: price(1500000),
+            total_deposit: 500,
+            deposit_shares: 330,
+            deposit_fee: 5,
+            total_redeem: 1500,
+            filled_shares: 1000,
+            funded_asset: 1500,
+            fill_ratio: 666666,
+        }
+    );
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_setle_epoch"), &treasury).into_val(&e),
+                (
+                    preview.epoch_id,
+                    preview.total_redeem,
+                    preview.filled_shares,
+                    preview.funded_asset,
+                    preview.price,
+                    preview.fill_ratio
+                )
+                    .into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(vault.get_epoch_deposit(&1).unwrap().shares_left, 330);
+    assert_eq!(buy_token_client.balance(&treasury), 1);
+
+    // claims are quoted from what is left of the epoch fills
+    assert_eq!(
+        vault.preview_claim(&user1, &buy_token_client.address),
+        (333, 489, 10)
+    );
+    vault.claim_request(&user1, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&user1), 10000 - 1001 + 489);
+    assert_eq!(
+        vault.preview_claim(&user1, &buy_token_client.address),
+        (0, 0, 0)
+    );
+
+    buy_token_admin.mint(&treasury, &10000);
+    vault.setle_epoch(&treasury);
+    for (user, request_epoch_id) in [(&user2, 1_u32), (&user3, 1_u32), (&user1, 2_u32)] {
+        let (filled_shares, buy_token_amount, fee) =
+            vault.preview_claim(user, &buy_token_client.address);
+        let balance = buy_token_client.balance(user);
+        vault.claim_request(user, &buy_token_client.address);
+        assert_eq!(
+            vec![&e, e.events().all().last().unwrap()],
+            vec![
+                &e,
+                (
+                    vault.address.clone(),
+                    (
+                        Symbol::new(&e, "vault_claim_request"),
+                        user,
+                        &buy_token_client.address
+                    )
+                        .into_val(&e),
+                    (request_epoch_id, filled_shares, buy_token_amount, fee).into_val(&e)
+                )
+            ]
+        );
+        assert_eq!(buy_token_client.balance(user), balance + buy_token_amount);
+    }
+}
+
+#[test]
+fn test_price_decimals() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    buy_token_admin.mint(&buyer, &100_0000000);
+
+    // shares with 9 decimals bought with a buy token of 7 decimals
+    let vault_address = Address::generate(&e);
+    let initial_price = Price {
+        mantissa: 25,
+        decimals: 1,
+    };
+    #[cfg(not(feature = "legacy-initialize"))]
+    e.register_at(
+        &vault_address,
+        crate::vault::Vault,
+        (
+            &seller,
+            &seller,
+            &treasury,
+            vault_address.clone(),
+            buy_token_client.address.clone(),
+            initial_price.clone(),
+        ),
+    );
+    #[cfg(feature = "legacy-initialize")]
+    e.register_at(&vault_address, crate::vault::Vault, ());
+    let vault = VaultClient::new(&e, &vault_address);
+    #[cfg(feature = "legacy-initialize")]
+    vault.initialize(
+        &seller,
+        &treasury,
+        &vault_address,
+        &buy_token_client.address,
+        &initial_price,
+    );
+    let shares = token::Client::new(&e, &vault_address);
+    assert_eq!(shares.decimals(), 7);
+    vault.set_share_metadata(
+        &seller,
+        &9,
+        &String::from_str(&e, "Vault Share"),
+        &String::from_str(&e, "VSH"),
+    );
+    assert_eq!(shares.decimals(), 9);
+    assert_eq!(vault.get_offer().sell_decimals, 9);
+    assert_eq!(vault.get_offer().buy_decimals, 7);
+
+    // 1 buy token at 2.5 buys 0.4 shares
+    vault.deposit(&buyer, &buy_token_client.address, &1_0000000, &0);
+    assert_eq!(shares.balance(&buyer), 400000000);
+    assert_eq!(
+        vault.preview_redeem(&buy_token_client.address, &100000000),
+        (2500000, 0)
+    );
+    vault.redeem_request(&buyer, &buy_token_client.address, &100000000);
+    vault.setle_epoch(&treasury);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(buy_token_client.balance(&buyer), 99_2500000);
+
+    // prices are no longer bounded by u32
+    vault.updt_price(
+        &seller,
+        &Price {
+            mantissa: 5_000_000_000,
+            decimals: 0,
+        },
+    );
+    assert_eq!(
+        vault.preview_deposit(&buy_token_client.address, &50_0000000),
+        (10, 0)
+    );
+    assert_eq!(
+        vault.try_updt_price(
+            &seller,
+            &Price {
+                mantissa: 1,
+                decimals: 19,
+            }
+        ),
+        Err(Ok(Error::InvalidPrice))
+    );
+    assert_eq!(
+        vault.try_updt_price(
+            &seller,
+            &Price {
+                mantissa: -1,
+                decimals: 6,
+            }
+        ),
+        Err(Ok(Error::InvalidPrice))
+    );
+    assert_eq!(
+        vault.try_updt_price(&seller, &price(0)),
+        Err(Ok(Error::ZeroPrice))
+    );
+}
+
+#[test]
+fn test_migrate_prices() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(2000000),
+    );
+    sell_token_admin.mint(&vault.address, &1000);
+    sell_token_admin.mint(&buyer, &100);
+    buy_token_admin.mint(&buyer, &100);
+    vault.deposit_request(&buyer, &100);
+    vault.redeem_request(&buyer, &buy_token_client.address, &40);
+    vault.setle_epoch(&treasury);
+
+    // rewrite the storage as a version 4 vault, with u32 prices of 6 decimals
+    e.as_contract(&vault.address, || {
+        write_legacy_offer(&e, 2000000);
+        e.storage()
+            .persistent()
+            .set(&DataKey::RedeemRate(1), &2000000_u32);
+        e.storage().persistent().set(
+            &DataKey::EpochDeposit(1),
+            &LegacyEpochDeposit {
+                rate: 2000000,
+                assets_left: 100,
+                shares_left: 50,
+            },
+        );
+        e.storage().persistent().set(
+            &DataKey::EpochFill(1),
+            &LegacyEpochFill {
+                fill_ratio: 1000000,
+                shares_left: 40,
+                filled_left: 40,
+            },
+        );
+        // reserves only exist from version 7
+        for token in [&sell_token_client.address, &buy_token_client.address] {
+            e.storage()
+                .instance()
+                .remove(&DataKey::Reserved(token.clone()));
+        }
+        e.storage().instance().set(&DataKey::SchemaVersion, &4_u32);
+    });
+
+    vault.migrate(&seller);
+    assert_eq!(vault.get_schema_version(), SCHEMA_VERSION);
+    let offer = vault.get_offer();
+    assert_eq!(offer.price, price(2000000));
+    assert_eq!(offer.sell_decimals, 7);
+    assert_eq!(offer.buy_decimals, 7);
+    assert_eq!(
+        vault.get_redeem_rate(&buy_token_client.address, &1),
+        price(2000000)
+    );
+    assert_eq!(vault.get_deposit_rate(&1), price(2000000));
+    assert_eq!(
+        vault.get_epoch_fill(&buy_token_client.address, &1),
+        Some(EpochFill {
+            fill_ratio: 1000000,
+            shares_left: 40,
+            filled_left: 40,
+            assets_left: 80,
+        })
+    );
+
+    // settled requests claim as before
+    vault.claim_deposit(&buyer);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(sell_token_client.balance(&buyer), 110);
+    assert_eq!(buy_token_client.balance(&buyer), 80);
+}
+
+// xorshift generator for the randomized tests, seeded so failures can be replayed
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+#[test]
+fn test_rounding() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    // the helpers round in the requested direction, with a 256 bit intermediate
+    let mut rng = Rng(0x5eed);
+    for _ in 0..200 {
+        let a = rng.below(2_000_000_000) as i128 - 1_000_000_000;
+        let b = rng.below(2_000_000) as i128 - 1_000_000;
+        let c = rng.below(1_000_000) as i128 + 1;
+        assert_eq!(mul_div_floor(&e, a, b, c), Ok((a * b).div_euclid(c)));
+        assert_eq!(mul_div_ceil(&e, a, b, c), Ok(-(-a * b).div_euclid(c)));
+        assert_eq!(mul_div_floor(&e, a, b, -c), Ok((-a * b).div_euclid(c)));
+    }
+    assert_eq!(mul_div_floor(&e, i128::MAX, 3, 3), Ok(i128::MAX));
+    assert_eq!(mul_div_ceil(&e, i128::MAX, 2, 3), Ok(i128::MAX / 3 * 2 + 1));
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1500000),
+    );
+
+    // fees are rounded up, shares bought are rounded down
+    vault.set_fees(&seller, &100, &100);
+    assert_eq!(vault.preview_fees(&101), (2, 2));
+    assert_eq!(
+        vault.preview_deposit(&buy_token_client.address, &101),
+        (66, 2)
+    );
+
+    // each claim of 1.5 buy tokens is rounded down, the funded half stays as dust
+    vault.set_fees(&seller, &0, &0);
+    let users = [
+        Address::generate(&e),
+        Address::generate(&e),
+        Address::generate(&e),
+    ];
+    for user in users.iter() {
+        sell_token_admin.mint(user, &1);
+        vault.redeem_request(user, &buy_token_client.address, &1);
+    }
+    buy_token_admin.mint(&treasury, &100);
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vault
+            .get_epoch_fill(&buy_token_client.address, &1)
+            .unwrap()
+            .assets_left,
+        4
+    );
+    for user in users.iter() {
+        assert_eq!(vault.get_dust(&buy_token_client.address), 0);
+        vault.claim_request(user, &buy_token_client.address);
+        assert_eq!(buy_token_client.balance(user), 1);
+    }
+    assert_eq!(vault.get_dust(&buy_token_client.address), 1);
+    assert_eq!(
+        vault
+            .get_epoch_fill(&buy_token_client.address, &1)
+            .unwrap()
+            .assets_left,
+        0
+    );
+
+    vault.sweep_dust(&seller, &buy_token_client.address);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_sweep_dust"),
+                    &seller,
+                    &buy_token_client.address
+                )
+                    .into_val(&e),
+                (&seller, 1_i128).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(buy_token_client.balance(&seller), 1);
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+    assert_eq!(vault.get_dust(&buy_token_client.address), 0);
+    assert_eq!(
+        vault.try_sweep_dust(&seller, &buy_token_client.address),
+        Err(Ok(Error::NoDust))
+    );
+    assert_eq!(
+        vault.try_sweep_dust(&treasury, &buy_token_client.address),
+        Err(Ok(Error::Unauthorized))
+    );
+}
+
+#[test]
+fn test_solvency() {
+    for seed in [1_u64, 0xdead_beef, 0x0123_4567_89ab] {
+        let e = Env::default();
+        e.mock_all_auths();
+        let mut rng = Rng(seed);
+
+        let token_admin = Address::generate(&e);
+        let seller = Address::generate(&e);
+        let treasury = Address::generate(&e);
+        let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+        let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+        let vault = create_vault_contract(
+            &e,
+            &seller,
+            &treasury,
+            &sell_token_client.address,
+            &buy_token_client.address,
+            &price(1000000 + rng.below(1000000) as i128),
+        );
+        vault.set_fees(&seller, &(rng.below(300) as u32), &(rng.below(300) as u32));
+        sell_token_admin.mint(&vault.address, &100_000_000);
+        let users = [
+            Address::generate(&e),
+            Address::generate(&e),
+            Address::generate(&e),
+        ];
+        for user in users.iter() {
+            buy_token_admin.mint(user, &1_000_000);
+            sell_token_admin.mint(user, &10_000);
+        }
+
+        // the vault always holds the escrowed deposits, what the settled requests can
+        // claim and the dust
+        let check_solvency = || {
+            let mut owed = vault.get_total_deposit() + vault.get_next_total_deposit();
+            for user in users.iter() {
+                let (_, buy_token_amount, fee) =
+                    vault.preview_claim(user, &buy_token_client.address);
+                owed += buy_token_amount + fee;
+            }
+            owed += vault.get_dust(&buy_token_client.address);
+            assert!(buy_token_client.balance(&vault.address) >= owed);
+
+            // the escrowed shares stay in the vault and no share is created or lost
+            let escrowed = vault.get_total_redeem(&buy_token_client.address)
+                + vault.get_next_total_redeem(&buy_token_client.address);
+            let reserved = vault.get_reserved(&sell_token_client.address);
+            assert!(reserved >= escrowed);
+            assert!(sell_token_client.balance(&vault.address) >= reserved);
+            let mut shares =
+                sell_token_client.balance(&vault.address) + sell_token_client.balance(&seller);
+            for user in users.iter() {
+                shares += sell_token_client.balance(user);
+            }
+            assert_eq!(shares, 100_030_000);
+        };
+
+        // successful deposits, redeems, settlements and claims
+        let mut done = [0_u32; 4];
+        for _ in 0..60 {
+            let user = &users[rng.below(3) as usize];
+            let amount = rng.below(1000) as i128 + 1;
+            let (kind, ok) = match rng.below(7) {
+                0 => (
+                    0,
+                    vault
+                        .try_deposit(user, &buy_token_client.address, &amount, &0)
+                        .is_ok(),
+                ),
+                1 => (0, vault.try_deposit_request(user, &amount).is_ok()),
+                2 => (
+                    1,
+                    vault
+                        .try_redeem_request(user, &buy_token_client.address, &amount)
+                        .is_ok(),
+                ),
+                3 => (
+                    3,
+                    vault
+                        .try_claim_request(user, &buy_token_client.address)
+                        .is_ok(),
+                ),
+                4 => (3, vault.try_claim_deposit(user).is_ok()),
+                5 => {
+                    // the treasury funds the epochs partially or in full
+                    buy_token_admin.mint(&treasury, &(rng.below(2000) as i128));
+                    (2, vault.try_setle_epoch(&treasury).is_ok())
+                }
+                _ => {
+                    let mantissa = 500000 + rng.below(2500000) as i128;
+                    vault.updt_price(&seller, &price(mantissa));
+                    check_solvency();
+                    continue;
+                }
+            };
+            if ok {
+                done[kind] += 1;
+            }
+            check_solvency();
+        }
+
+        // once everything is settled and claimed the vault holds exactly the dust
+        buy_token_admin.mint(&treasury, &100_000_000);
+        vault.setle_epoch(&treasury);
+        for user in users.iter() {
+            if vault
+                .try_claim_request(user, &buy_token_client.address)
+                .is_ok()
+            {
+                done[3] += 1;
+            }
+            if vault.try_claim_deposit(user).is_ok() {
+                done[3] += 1;
+            }
+        }
+        assert!(done.iter().all(|count| *count > 0), "{:?}", done);
+        check_solvency();
+        let dust = vault.get_dust(&buy_token_client.address);
+        assert_eq!(buy_token_client.balance(&vault.address), dust);
+        if dust > 0 {
+            vault.sweep_dust(&seller, &buy_token_client.address);
+        }
+        assert_eq!(buy_token_client.balance(&vault.address), 0);
+    }
+}
+
+#[test]
+fn test_errors() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+
+    // before initialization there is no offer to read
+    #[cfg(feature = "legacy-initialize")]
+    {
+        let uninitialized = VaultClient::new(&e, &e.register(crate::vault::Vault, ()));
+        assert_eq!(
+            uninitialized.try_get_price(&buy_token_client.address),
+            Err(Ok(Error::OfferNotCreated))
+        );
+        assert_eq!(
+            uninitialized.try_get_epoch_id(),
+            Err(Ok(Error::OfferNotCreated))
+        );
+    }
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(500000),
+    );
+    buy_token_admin.mint(&buyer, &1000);
+
+    // the vault cannot hand out sell tokens it does not hold
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &10, &0),
+        Err(Ok(Error::InsufficientLiquidity))
+    );
+    assert_eq!(
+        vault.try_claim_leftover(&seller, &buy_token_client.address, &1),
+        Err(Ok(Error::InsufficientSurplus))
+    );
+    assert_eq!(buy_token_client.balance(&buyer), 1000);
+
+    // amounts that do not fit in 128 bits fail instead of wrapping
+    assert_eq!(
+        vault.try_preview_deposit(&buy_token_client.address, &i128::MAX),
+        Err(Ok(Error::Overflow))
+    );
+    sell_token_admin.mint(&seller, &1000);
+    sell_token_client.transfer(&seller, &vault.address, &1000);
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token_client.address, &10, &21),
+        Err(Ok(Error::PriceTooLow))
+    );
+    vault.deposit(&buyer, &buy_token_client.address, &10, &20);
+
+    // negative amounts are rejected before any limit is checked
+    assert_eq!(
+        vault.try_deposit_request(&buyer, &-1),
+        Err(Ok(Error::NegativeAmount))
+    );
+    assert_eq!(
+        vault.try_redeem_request(&buyer, &buy_token_client.address, &-1),
+        Err(Ok(Error::NegativeRedeemAmount))
+    );
+
+    // a pending deposit request cannot be topped up once it rolls into the next epoch
+    vault.set_schedule(&seller, &1000, &100, &50, &80);
+    e.ledger().set_timestamp(1000);
+    vault.deposit_request(&buyer, &10);
+    e.ledger().set_timestamp(1051);
+    assert_eq!(
+        vault.try_deposit_request(&buyer, &10),
+        Err(Ok(Error::RequestInOtherEpoch))
+    );
+
+    // calls the vault does not support or cannot answer yet
+    assert_eq!(
+        vault.try_set_share_metadata(
+            &seller,
+            &6,
+            &String::from_str(&e, "Vault Share"),
+            &String::from_str(&e, "VS"),
+        ),
+        Err(Ok(Error::NotShareToken))
+    );
+    assert_eq!(
+        vault.try_get_redeem_rate(&buy_token_client.address, &1),
+        Err(Ok(Error::EpochNotSetled))
+    );
+    assert_eq!(
+        vault.try_get_deposit_rate(&1),
+        Err(Ok(Error::EpochNotSetled))
+    );
+}
+
+#[test]
+fn test_multi_asset() {
+    let e = Env::default();
+    // the token's treasury authorizes its transfer inside the settler's call
+    e.mock_all_auths_allowing_non_root_auth();
+    e.ledger().set_timestamp(1_000_000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let usdc_treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let (usdc_client, usdc_admin) = create_token_contract(&e, &token_admin);
+    let (other_client, _) = create_token_contract(&e, &token_admin);
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token_client.address,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &1000);
+    buy_token_admin.mint(&buyer, &1000);
+    usdc_admin.mint(&buyer, &1000);
+
+    assert_eq!(
+        vault.try_add_buy_token(
+            &treasury,
+            &usdc_client.address,
+            &price(2000000),
+            &usdc_treasury
+        ),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.add_buy_token(
+        &seller,
+        &usdc_client.address,
+        &price(2000000),
+        &usdc_treasury,
+    );
+    assert_eq!(
+        vault.try_add_buy_token(
+            &seller,
+            &usdc_client.address,
+            &price(2000000),
+            &usdc_treasury
+        ),
+        Err(Ok(Error::TokenAlreadyAccepted))
+    );
+    assert_eq!(
+        vault.try_add_buy_token(
+            &seller,
+            &buy_token_client.address,
+            &price(1000000),
+            &treasury
+        ),
+        Err(Ok(Error::TokenAlreadyAccepted))
+    );
+    assert_eq!(
+        vault.get_buy_tokens(),
+        vec![&e, usdc_client.address.clone()]
+    );
+    assert_eq!(
+        vault.get_buy_token(&usdc_client.address),
+        BuyToken {
+            price: price(2000000),
+            decimals: 7,
+            treasury: usdc_treasury.clone(),
+            enabled: true,
+        }
+    );
+    assert_eq!(
+        vault.try_get_buy_token(&other_client.address),
+        Err(Ok(Error::TokenNotAccepted))
+    );
+    assert_eq!(
+        vault.try_deposit(&buyer, &other_client.address, &100_i128, &0_i128),
+        Err(Ok(Error::TokenNotAccepted))
+    );
+
+    // deposits in the added token are priced in it and go to its own treasury
+    assert_eq!(vault.get_price(&usdc_client.address), price(2000000));
+    vault.deposit(&buyer, &usdc_client.address, &100_i128, &50_i128);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_deposit"),
+                    &buyer,
+                    &usdc_treasury,
+                    &usdc_client.address
+                )
+                    .into_val(&e),
+                (100_i128, 50_i128, 0_i128).into_val(&e)
+            )
+        ]
+    );
+    vault.deposit(&buyer, &buy_token_client.address, &10_i128, &10_i128);
+    assert_eq!(sell_token_client.balance(&buyer), 60);
+    assert_eq!(usdc_client.balance(&usdc_treasury), 100);
+    assert_eq!(buy_token_client.balance(&treasury), 10);
+
+    // each token has its own redemption queue
+    vault.redeem_request(&buyer, &usdc_client.address, &20_i128);
+    vault.redeem_request(&buyer, &buy_token_client.address, &5_i128);
+    assert_eq!(vault.get_total_redeem(&usdc_client.address), 20);
+    assert_eq!(vault.get_total_redeem(&buy_token_client.address), 5);
+
+    vault.setle_epoch(&treasury);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_setle_token"),
+                    &treasury,
+                    &usdc_client.address
+                )
+                    .into_val(&e),
+                (
+                    1_u32,
+                    20_i128,
+                    20_i128,
+                    40_i128,
+                    price(2000000),
+                    1000000_u32
+                )
+                    .into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(
+        vault.get_redeem_rate(&usdc_client.address, &1),
+        price(2000000)
+    );
+    assert_eq!(vault.get_total_redeem(&usdc_client.address), 0);
+    assert_eq!(usdc_client.balance(&usdc_treasury), 60);
+
+    vault.claim_request(&buyer, &usdc_client.address);
+    vault.claim_request(&buyer, &buy_token_client.address);
+    assert_eq!(usdc_client.balance(&buyer), 940);
+    assert_eq!(buy_token_client.balance(&buyer), 995);
+    assert_eq!(sell_token_client.balance(&buyer), 35);
+
+    // a disabled token takes no new deposits or redeem requests
+    vault.disable_buy_token(&seller, &usdc_client.address);
+    assert_eq!(
+        vault.try_deposit(&buyer, &usdc_client.address, &100_i128, &0_i128),
+        Err(Ok(Error::TokenDisabled))
+    );
+    assert_eq!(
+        vault.try_redeem_request(&buyer, &usdc_client.address, &5_i128),
+        Err(Ok(Error::TokenDisabled))
+    );
+    vault.enable_buy_token(&seller, &usdc_client.address);
+
+    // the price and the oracle of the added token are managed separately
+    vault.set_buy_token_price(&seller, &usdc_client.address, &price(2500000));
+    assert_eq!(vault.get_price(&usdc_client.address), price(2500000));
+    assert_eq!(vault.get_price(&buy_token_client.address), price(1000000));
+
+    let oracle = MockOracleClient::new(&e, &e.register(MockOracle, ()));
+    oracle.set_price(&240_000_000_000_000, &999_000);
+    let config = OracleConfig {
+        oracle: oracle.address.clone(),
+        asset: Asset::Stellar(sell_token_client.address.clone()),
+        max_age: 3600,
+        max_deviation_bps: 500,
+        allow_fallback: false,
+    };
+    vault.set_buy_token_oracle(&seller, &usdc_client.address, &Some(config.clone()));
+    assert_eq!(
+        vault.get_buy_token_oracle(&usdc_client.address),
+        Some(config)
+    );
+    assert_eq!(vault.get_oracle(), None);
+    assert_eq!(
+        vault.get_price(&usdc_client.address),
+        Price {
+            mantissa: 240_000_000_000_000,
+            decimals: 14
+        }
+    );
+    vault.set_buy_token_oracle(&seller, &usdc_client.address, &None);
+    assert_eq!(vault.get_price(&usdc_client.address), price(2500000));
+}
+
+#[test]
+fn test_setle_epoch_treasury_auth() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let usdc_treasury = Address::generate(&e);
+    let keeper = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let (usdc_client, usdc_admin) = create_token_contract(&e, &token_admin);
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &1000);
+    buy_token_admin.mint(&buyer, &1000);
+    buy_token_admin.mint(&treasury, &1000);
+    usdc_admin.mint(&buyer, &1000);
+    vault.add_buy_token(
+        &seller,
+        &usdc_client.address,
+        &price(1000000),
+        &usdc_treasury,
+    );
+    vault.grant_role(&seller, &Role::Settler, &keeper);
+    vault.deposit(&buyer, &buy_token, &100, &100);
+    vault.deposit(&buyer, &usdc_client.address, &100, &100);
+
+    // an epoch without redemptions needs no treasury signature
+    let setle = MockAuthInvoke {
+        contract: &vault.address,
+        fn_name: "setle_epoch",
+        args: (&keeper,).into_val(&e),
+        sub_invokes: &[],
+    };
+    vault
+        .mock_auths(&[MockAuth {
+            address: &keeper,
+            invoke: &setle,
+        }])
+        .setle_epoch(&keeper);
+    assert_eq!(vault.get_epoch_id(), 2);
+
+    // only the treasury of the token funding redemptions signs
+    vault.redeem_request(&buyer, &buy_token, &40);
+    vault
+        .mock_auths(&[
+            MockAuth {
+                address: &keeper,
+                invoke: &setle,
+            },
+            MockAuth {
+                address: &treasury,
+                invoke: &MockAuthInvoke {
+                    contract: &buy_token,
+                    fn_name: "transfer",
+                    args: (&treasury, &vault.address, 40_i128).into_val(&e),
+                    sub_invokes: &[],
+                },
+            },
+        ])
+        .setle_epoch(&keeper);
+    assert_eq!(vault.get_epoch_id(), 3);
+    assert_eq!(buy_token_client.balance(&treasury), 1060);
+    assert_eq!(usdc_client.balance(&usdc_treasury), 100);
+}
+
+#[test]
+fn test_reserved() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let sell_token = sell_token_client.address.clone();
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token,
+        &buy_token,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &1000);
+    buy_token_admin.mint(&buyer, &1000);
+    buy_token_admin.mint(&treasury, &1000);
+
+    // escrowed redeem shares and deposits are reserved
+    vault.deposit(&buyer, &buy_token, &100, &100);
+    vault.redeem_request(&buyer, &buy_token, &40);
+    vault.deposit_request(&buyer, &50);
+    assert_eq!(vault.get_reserved(&sell_token), 40);
+    assert_eq!(vault.get_reserved(&buy_token), 50);
+    assert_eq!(
+        vault.try_claim_leftover(&seller, &buy_token, &1),
+        Err(Ok(Error::InsufficientSurplus))
+    );
+
+    // settlement sends the deposits to the treasury and reserves the funded buy tokens and
+    // the settled deposit shares instead
+    vault.setle_epoch(&treasury);
+    assert_eq!(sell_token_client.balance(&vault.address), 900);
+    assert_eq!(vault.get_reserved(&sell_token), 50);
+    assert_eq!(vault.get_reserved(&buy_token), 40);
+
+    // a vault upgraded from version 6 rebuilds its reserves
+    e.as_contract(&vault.address, || {
+        let storage = e.storage().instance();
+        storage.remove(&DataKey::Reserved(sell_token.clone()));
+        storage.remove(&DataKey::Reserved(buy_token.clone()));
+        storage.set(&DataKey::SchemaVersion, &6_u32);
+    });
+    vault.migrate(&seller);
+    assert_eq!(vault.get_reserved(&sell_token), 50);
+    assert_eq!(vault.get_reserved(&buy_token), 40);
+
+    // only the surplus can be swept
+    assert_eq!(
+        vault.try_claim_leftover(&seller, &sell_token, &851),
+        Err(Ok(Error::InsufficientSurplus))
+    );
+    vault.claim_leftover(&seller, &sell_token, &850);
+    buy_token_admin.mint(&vault.address, &7);
+    assert_eq!(
+        vault.try_claim_leftover(&seller, &buy_token, &8),
+        Err(Ok(Error::InsufficientSurplus))
+    );
+    vault.claim_leftover(&seller, &buy_token, &7);
+    assert_eq!(buy_token_client.balance(&seller), 7);
+
+    // claims release the reserves
+    vault.claim_deposit(&buyer);
+    vault.claim_request(&buyer, &buy_token);
+    assert_eq!(vault.get_reserved(&sell_token), 0);
+    assert_eq!(vault.get_reserved(&buy_token), 0);
+    assert_eq!(sell_token_client.balance(&buyer), 110);
+    assert_eq!(buy_token_client.balance(&buyer), 890);
+    assert_eq!(sell_token_client.balance(&vault.address), 0);
+    assert_eq!(buy_token_client.balance(&vault.address), 0);
+}
+
+#[test]
+fn test_deposit_reserved_shares() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let holder = Address::generate(&e);
+    let buyer = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&holder, &50);
+    buy_token_admin.mint(&buyer, &100);
+
+    // the vault only holds the escrowed shares, which deposits cannot buy
+    vault.redeem_request(&holder, &buy_token, &50);
+    assert_eq!(sell_token_client.balance(&vault.address), 50);
+    assert_eq!(vault.get_reserved(&sell_token_client.address), 50);
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token, &50, &0),
+        Err(Ok(Error::InsufficientLiquidity))
+    );
+
+    // only the surplus is sold
+    sell_token_admin.mint(&vault.address, &10);
+    assert_eq!(
+        vault.try_deposit(&buyer, &buy_token, &11, &0),
+        Err(Ok(Error::InsufficientLiquidity))
+    );
+    vault.deposit(&buyer, &buy_token, &10, &10);
+    assert_eq!(sell_token_client.balance(&buyer), 10);
+
+    vault.cancel_request(&holder);
+    assert_eq!(sell_token_client.balance(&holder), 50);
+    assert_eq!(sell_token_client.balance(&vault.address), 0);
+}
+
+#[test]
+fn test_operators() {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().set_timestamp(1000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let owner = Address::generate(&e);
+    let operator = Address::generate(&e);
+    let custodian = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token,
+        &price(1000000),
+    );
+    sell_token_admin.mint(&vault.address, &1000);
+    buy_token_admin.mint(&owner, &1000);
+    buy_token_admin.mint(&treasury, &1000);
+    vault.deposit(&owner, &buy_token, &100, &100);
+
+    assert_eq!(
+        vault.try_redeem_request_for(&operator, &owner, &buy_token, &30),
+        Err(Ok(Error::Unauthorized))
+    );
+    let scopes = vec![
+        &e,
+        OperatorScope::Redeem,
+        OperatorScope::Cancel,
+        OperatorScope::Claim,
+    ];
+    assert_eq!(
+        vault.try_approve_operator(&owner, &operator, &scopes, &1000),
+        Err(Ok(Error::InvalidExpiration))
+    );
+    vault.approve_operator(&owner, &operator, &scopes, &2000);
+    assert_eq!(
+        vault.get_operator(&owner, &operator, &OperatorScope::Claim),
+        Some(2000)
+    );
+
+    // the operator requests with the owner's allowance to the vault, only the operator signs
+    sell_token_client.approve(&owner, &vault.address, &100, &1000);
+    vault.redeem_request_for(&operator, &owner, &buy_token, &30);
+    assert_eq!(e.auths().len(), 1);
+    assert_eq!(e.auths()[0].0, operator);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_operator_action"), &operator, &owner).into_val(&e),
+                (Symbol::new(&e, "redeem_request"), &owner).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(sell_token_client.balance(&owner), 70);
+    assert_eq!(vault.get_request(&owner, &buy_token, &1).shares_amount, 30);
+
+    // cancelled shares go back to the owner
+    vault.cancel_request_for(&operator, &owner);
+    assert_eq!(sell_token_client.balance(&owner), 100);
+    vault.redeem_request_for(&operator, &owner, &buy_token, &30);
+    vault.setle_epoch(&treasury);
+
+    // claims pay the owner or a receiver the owner approved
+    assert_eq!(
+        vault.try_claim_request_for(&operator, &owner, &buy_token, &custodian),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.approve_receiver(&owner, &custodian);
+    assert!(vault.is_receiver(&owner, &custodian));
+    vault.claim_request_for(&operator, &owner, &buy_token, &custodian);
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (Symbol::new(&e, "vault_operator_action"), &operator, &owner).into_val(&e),
+                (Symbol::new(&e, "claim_request"), &custodian).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(buy_token_client.balance(&custodian), 30);
+    assert_eq!(buy_token_client.balance(&owner), 900);
+
+    // approvals lapse at their expiry and can be revoked
+    e.ledger().set_timestamp(2001);
+    assert_eq!(
+        vault.try_redeem_request_for(&operator, &owner, &buy_token, &10),
+        Err(Ok(Error::Unauthorized))
+    );
+    vault.revoke_operator(&owner, &operator, &scopes);
+    assert_eq!(
+        vault.get_operator(&owner, &operator, &OperatorScope::Redeem),
+        None
+    );
+    vault.revoke_receiver(&owner, &custodian);
+    assert!(!vault.is_receiver(&owner, &custodian));
+}
+
+#[test]
+fn test_process_claims() {
+    let e = Env::default();
+    e.mock_all_auths();
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    let user1 = Address::generate(&e);
+    let user2 = Address::generate(&e);
+    let user3 = Address::generate(&e);
+    let stranger = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token,
+        &price(1000000),
+    );
+    buy_token_admin.mint(&treasury, &1000);
+    for user in [&user1, &user2, &user3] {
+        sell_token_admin.mint(user, &100);
+        vault.redeem_request(user, &buy_token, &100);
+    }
+    assert_eq!(vault.get_epoch_requester_count(&buy_token, &1), 3);
+    assert_eq!(
+        vault.get_epoch_requesters(&buy_token, &1, &0, &10),
+        vec![&e, user1.clone(), user2.clone(), user3.clone()]
+    );
+
+    // a cancelled request leaves the index, the last requester takes its place
+    vault.cancel_request(&user1);
+    assert_eq!(vault.get_epoch_requester_count(&buy_token, &1), 2);
+    assert_eq!(
+        vault.get_epoch_requesters(&buy_token, &1, &0, &10),
+        vec![&e, user3.clone(), user2.clone()]
+    );
+    assert_eq!(
+        vault.get_epoch_requesters(&buy_token, &1, &1, &1),
+        vec![&e, user2.clone()]
+    );
+
+    // requesting again lists the user once more
+    vault.redeem_request(&user1, &buy_token, &100);
+    vault.cancel_request(&user3);
+    assert_eq!(
+        vault.get_epoch_requesters(&buy_token, &1, &0, &10),
+        vec![&e, user1.clone(), user2.clone()]
+    );
+    assert_eq!(vault.get_epoch_requester_count(&buy_token, &2), 0);
+
+    vault.setle_epoch(&treasury);
+    let users = vec![
+        &e,
+        user1.clone(),
+        user2.clone(),
+        user3.clone(),
+        stranger.clone(),
+    ];
+    assert_eq!(
+        vault.try_process_claims(&seller, &buy_token, &users),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    // users without a claimable request are skipped and returned
+    assert_eq!(
+        vault.process_claims(&treasury, &buy_token, &users),
+        vec![&e, user3.clone(), stranger.clone()]
+    );
+    assert_eq!(
+        vec![&e, e.events().all().last().unwrap()],
+        vec![
+            &e,
+            (
+                vault.address.clone(),
+                (
+                    Symbol::new(&e, "vault_process_claims"),
+                    &treasury,
+                    &buy_token
+                )
+                    .into_val(&e),
+                (2_u32, vec![&e, user3.clone(), stranger.clone()]).into_val(&e)
+            )
+        ]
+    );
+    assert_eq!(buy_token_client.balance(&user1), 100);
+    assert_eq!(buy_token_client.balance(&user2), 100);
+    assert_eq!(buy_token_client.balance(&user3), 0);
+
+    // claimed requests leave the index
+    assert_eq!(vault.get_epoch_requester_count(&buy_token, &1), 0);
+    assert_eq!(
+        vault.get_epoch_requesters(&buy_token, &1, &0, &10),
+        vec![&e]
+    );
+    assert_eq!(vault.process_claims(&treasury, &buy_token, &users), users);
+}
+
+#[test]
+fn test_epoch_records() {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().set_timestamp(5000);
+
+    let token_admin = Address::generate(&e);
+    let seller = Address::generate(&e);
+    let treasury = Address::generate(&e);
+
+    let (sell_token_client, sell_token_admin) = create_token_contract(&e, &token_admin);
+    let (buy_token_client, buy_token_admin) = create_token_contract(&e, &token_admin);
+    let buy_token = buy_token_client.address.clone();
+
+    let vault = create_vault_contract(
+        &e,
+        &seller,
+        &treasury,
+        &sell_token_client.address,
+        &buy_token,
+        &price(1333333),
+    );
+    vault.set_fees(&seller, &0, &150);
+
+    let users: std::vec::Vec<Address> = (0..7).map(|_| Address::generate(&e)).collect();
+    for (i, user) in users.iter().enumerate() {
+        sell_token_admin.mint(user, &1000);
+        vault.redeem_request(user, &buy_token, &(97 + 31 * i as i128));
+    }
+    assert_eq!(vault.get_epoch(&buy_token, &1), None);
+
+    // the first epoch is funded in part, the rest carries over to the second one
+    buy_token_admin.mint(&treasury, &700);
+    vault.setle_epoch(&treasury);
+    let first = vault.get_epoch(&buy_token, &1).unwrap();
+    assert_eq!(first.epoch_id, 1);
+    assert_eq!(first.total_redeem, 1330);
+    assert!(first.funded_asset > 0 && first.funded_asset <= 700);
+    assert_eq!(first.claimed_asset, 0);
+    assert_eq!(first.price, price(1333333));
+    assert_eq!(first.settled_at, 5000);
+    assert!(first.redeemed_shares < first.total_redeem);
+
+    e.ledger().set_timestamp(6000);
+    buy_token_admin.mint(&treasury, &10000);
+    vault.setle_epoch(&treasury);
+    let second = vault.get_epoch(&buy_token, &2).unwrap();
+    assert_eq!(
+        second.total_redeem,
+        first.total_redeem - first.redeemed_shares
+    );
+    assert_eq!(second.redeemed_shares, second.total_redeem);
+    assert_eq!(second.settled_at, 6000);
+    assert_eq!(
+        vault.get_epochs(&buy_token, &0, &u32::MAX),
+        vec![&e, first.clone(), second.clone()]
+    );
+
+    // claims never pay out more than an epoch was funded with, and all of it once every
+    // request is claimed, but for the rounding dust
+    for user in users.iter() {
+        vault.claim_request(user, &buy_token);
+        for info in vault.get_epochs(&buy_token, &1, &2).iter() {
+            assert!(info.claimed_asset <= info.funded_asset);
+        }
+    }
+    let claimed = vault.get_epoch(&buy_token, &1).unwrap().claimed_asset
+        + vault.get_epoch(&buy_token, &2).unwrap().claimed_asset;
+    let paid: i128 = users
+        .iter()
+        .map(|user| buy_token_client.balance(user))
+        .sum();
+    let fees =
+        buy_token_client.balance(&treasury) - (10700 - first.funded_asset - second.funded_asset);
+    assert_eq!(claimed, paid + fees);
+    assert_eq!(
+        claimed + vault.get_dust(&buy_token),
+        first.funded_asset + second.funded_asset
+    );
 }
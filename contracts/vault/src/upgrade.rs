@@ -0,0 +1,237 @@
+use crate::buy_token::read_buy_tokens;
+use crate::dust::read_dust;
+use crate::epoch::{
+    read_epoch_deposit, read_epoch_fill, read_redeem_rate, write_epoch_deposit, write_epoch_fill,
+    write_redeem_rate,
+};
+use crate::errors::Error;
+use crate::offer::{load_offer, read_token_decimals, write_offer};
+use crate::price::{legacy_price, shares_to_assets};
+use crate::redeem_request::write_legacy_requests;
+use crate::reserve::reserve;
+use crate::roles::write_initial_roles;
+use crate::share::is_share_token;
+use crate::storage_types::{
+    DataKey, EpochDeposit, EpochFill, LegacyEpochDeposit, LegacyEpochFill, LegacyOffer, Offer,
+    PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Address, Env};
+
+// version of the storage layout written by this contract code
+#[cfg(not(feature = "next-schema"))]
+pub const SCHEMA_VERSION: u32 = 7;
+#[cfg(feature = "next-schema")]
+pub const SCHEMA_VERSION: u32 = 8;
+
+// vaults created before the schema version was stored are on version 1
+pub fn read_schema_version(e: &Env) -> u32 {
+    e.storage()
+        .instance()
+        .get::<_, u32>(&DataKey::SchemaVersion)
+        .unwrap_or(1)
+}
+
+pub fn write_schema_version(e: &Env, version: u32) {
+    e.storage()
+        .instance()
+        .set(&DataKey::SchemaVersion, &version);
+}
+
+// convert the storage from `from_version` to the current layout, one version at a time
+pub fn migrate_storage(e: &Env, from_version: u32) -> Result<(), Error> {
+    let mut version = from_version;
+    while version < SCHEMA_VERSION {
+        match version {
+            1 => migrate_v1_to_v2(e)?,
+            2 => migrate_v2_to_v3(e),
+            3 => migrate_v3_to_v4(e),
+            4 => migrate_v4_to_v5(e)?,
+            5 => migrate_v5_to_v6(e)?,
+            6 => migrate_v6_to_v7(e)?,
+            _ => {}
+        }
+        version += 1;
+    }
+    write_schema_version(e, SCHEMA_VERSION);
+    Ok(())
+}
+
+// version 1 authorized privileged calls against the offer's seller and treasury,
+// grant them the equivalent roles
+fn migrate_v1_to_v2(e: &Env) -> Result<(), Error> {
+    let offer = read_legacy_offer(e)?;
+    write_initial_roles(e, &offer.seller, &offer.seller, &offer.treasury);
+    Ok(())
+}
+
+// version 2 kept redeem rates and requests in instance storage. Rates of settled epochs
+// are moved to persistent storage here, requests are moved by `migrate_v3_to_v4`.
+fn migrate_v2_to_v3(e: &Env) {
+    for settled_epoch in 1..read_epoch_id(e) {
+        let key = DataKey::RedeemRate(settled_epoch);
+        if let Some(rate) = e.storage().instance().get::<_, u32>(&key) {
+            e.storage().instance().remove(&key);
+            e.storage().persistent().set(&key, &rate);
+            e.storage().persistent().extend_ttl(
+                &key,
+                PERSISTENT_LIFETIME_THRESHOLD,
+                PERSISTENT_BUMP_AMOUNT,
+            );
+        }
+    }
+}
+
+// up to version 3 a sender had a single redeem request. Senders cannot be enumerated, so
+// each request is moved to its epoch the first time it is read.
+fn migrate_v3_to_v4(e: &Env) {
+    write_legacy_requests(e);
+}
+
+// up to version 4 prices were u32 with 6 decimals and amounts of both tokens were assumed to
+// have the same decimals. The offer gets the decimals of its tokens, and the rates of settled
+// epochs become prices with 6 decimals.
+fn migrate_v4_to_v5(e: &Env) -> Result<(), Error> {
+    let legacy = read_legacy_offer(e)?;
+    let (sell_decimals, buy_decimals) =
+        read_token_decimals(e, &legacy.sell_token, &legacy.buy_token);
+    let buy_token = legacy.buy_token.clone();
+    write_offer(
+        e,
+        &Offer {
+            seller: legacy.seller,
+            treasury: legacy.treasury,
+            sell_token: legacy.sell_token,
+            buy_token: legacy.buy_token,
+            price: legacy_price(legacy.price),
+            sell_decimals,
+            buy_decimals,
+        },
+    );
+
+    for settled_epoch in 1..read_epoch_id(e) {
+        let key = DataKey::RedeemRate(settled_epoch);
+        if let Some(rate) = e.storage().persistent().get::<_, u32>(&key) {
+            write_redeem_rate(e, &buy_token, settled_epoch, &legacy_price(rate));
+        }
+        let key = DataKey::EpochDeposit(settled_epoch);
+        if let Some(deposit) = e.storage().persistent().get::<_, LegacyEpochDeposit>(&key) {
+            write_epoch_deposit(
+                e,
+                settled_epoch,
+                &EpochDeposit {
+                    rate: legacy_price(deposit.rate),
+                    assets_left: deposit.assets_left,
+                    shares_left: deposit.shares_left,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+// version 6 tracks the buy tokens funded for each epoch and not paid out yet, so the rounding
+// dust left by its last claim can be swept. They are valued at the epoch's rate, which is never
+// more than what is left since payouts are rounded down.
+fn migrate_v5_to_v6(e: &Env) -> Result<(), Error> {
+    let offer = load_offer(e)?;
+    for settled_epoch in 1..read_epoch_id(e) {
+        let key = DataKey::EpochFill(settled_epoch);
+        let Some(fill) = e.storage().persistent().get::<_, LegacyEpochFill>(&key) else {
+            continue;
+        };
+        let assets_left = match read_redeem_rate(e, &offer.buy_token, settled_epoch) {
+            Some(rate) => shares_to_assets(e, &offer, &rate, fill.filled_left)?,
+            None => 0,
+        };
+        write_epoch_fill(
+            e,
+            &offer.buy_token,
+            settled_epoch,
+            &EpochFill {
+                fill_ratio: fill.fill_ratio,
+                shares_left: fill.shares_left,
+                filled_left: fill.filled_left,
+                assets_left,
+            },
+        );
+    }
+    Ok(())
+}
+
+// version 7 keeps the tokens held for investors out of reach of `claim_leftover`. The reserves
+// are the escrowed redeem shares of every buy token, the settled deposit shares not claimed yet
+// of a pre-minted sell token, the escrowed deposits, and the funded buy tokens not paid out yet
+// with the dust.
+fn migrate_v6_to_v7(e: &Env) -> Result<(), Error> {
+    let offer = load_offer(e)?;
+    let epoch_id = read_epoch_id(e);
+    let mut escrowed_shares = sum_totals(e, &[DataKey::TotalRedeem, DataKey::NextTotalRedeem])?;
+    if !is_share_token(e, &offer) {
+        for settled_epoch in 1..epoch_id {
+            if let Some(deposit) = read_epoch_deposit(e, settled_epoch) {
+                escrowed_shares = escrowed_shares
+                    .checked_add(deposit.shares_left)
+                    .ok_or(Error::Overflow)?;
+            }
+        }
+    }
+    let escrowed_deposits = sum_totals(e, &[DataKey::TotalDeposit, DataKey::NextTotalDeposit])?;
+    let owed_assets = read_owed_assets(e, &offer.buy_token, epoch_id)?;
+    reserve(
+        e,
+        &offer.buy_token,
+        escrowed_deposits
+            .checked_add(owed_assets)
+            .ok_or(Error::Overflow)?,
+    )?;
+
+    for token in read_buy_tokens(e).iter() {
+        let token_shares = sum_totals(
+            e,
+            &[
+                DataKey::TokenTotalRedeem(token.clone()),
+                DataKey::TokenNextTotalRedeem(token.clone()),
+            ],
+        )?;
+        escrowed_shares = escrowed_shares
+            .checked_add(token_shares)
+            .ok_or(Error::Overflow)?;
+        reserve(e, &token, read_owed_assets(e, &token, epoch_id)?)?;
+    }
+    reserve(e, &offer.sell_token, escrowed_shares)
+}
+
+fn sum_totals(e: &Env, keys: &[DataKey]) -> Result<i128, Error> {
+    let mut sum: i128 = 0;
+    for key in keys {
+        let total = e.storage().instance().get::<_, i128>(key).unwrap_or(0);
+        sum = sum.checked_add(total).ok_or(Error::Overflow)?;
+    }
+    Ok(sum)
+}
+
+// buy tokens funded for the settled epochs and not paid out yet, with the dust
+fn read_owed_assets(e: &Env, token: &Address, epoch_id: u32) -> Result<i128, Error> {
+    let mut owed = read_dust(e, token);
+    for settled_epoch in 1..epoch_id {
+        if let Some(fill) = read_epoch_fill(e, token, settled_epoch) {
+            owed = owed.checked_add(fill.assets_left).ok_or(Error::Overflow)?;
+        }
+    }
+    Ok(owed)
+}
+
+// the offer of a vault that is not migrated to version 5 yet
+pub fn read_legacy_offer(e: &Env) -> Result<LegacyOffer, Error> {
+    e.storage()
+        .instance()
+        .get::<_, LegacyOffer>(&DataKey::Offer)
+        .ok_or(Error::OfferNotCreated)
+}
+
+fn read_epoch_id(e: &Env) -> u32 {
+    e.storage()
+        .instance()
+        .get::<_, u32>(&DataKey::EpochId)
+        .unwrap_or(1)
+}
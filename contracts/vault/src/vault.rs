@@ -1,282 +1,2282 @@
+use crate::allowance::{read_allowance, spend_allowance, write_allowance};
+use crate::balance::{
+    burn_balance, check_nonnegative_amount, read_balance, read_total_supply, receive_balance,
+    spend_balance,
+};
+use crate::buy_token::{
+    accepted_tokens, is_base_token, load_market, load_open_market, read_buy_token, read_buy_tokens,
+    write_buy_token,
+};
+use crate::compliance::{
+    is_denied, read_allowed, read_compliance, remove_allowed, require_compliant,
+    require_not_denied, write_allowed, write_compliance, write_denied,
+};
+use crate::deposit_request::{
+    delete_deposit_request, extend_deposit_request, read_deposit_request, write_deposit_request,
+};
+use crate::dust::{add_dust, read_dust, take_dust};
+use crate::epoch::{
+    add_epoch_claim, can_settle, extend_epoch_deposit, extend_epoch_fill, extend_epoch_info,
+    extend_redeem_rate, fill_shares, read_epoch_deposit, read_epoch_fill, read_epoch_info,
+    read_epoch_window, read_redeem_rate, read_schedule, request_epoch_id, start_next_epoch,
+    take_deposit_shares, write_epoch_deposit, write_epoch_fill, write_epoch_info,
+    write_epoch_start, write_redeem_rate, write_schedule, FULL_FILL,
+};
 use crate::errors::Error;
-use crate::offer::{load_offer, write_offer};
-use crate::redeem_request::{delete_redeem_request, read_redeem_request, write_redeem_request};
-use crate::storage_types::{DataKey, Offer, RedeemRequest};
+use crate::fees::{fee_amount, read_fees, write_fees, MAX_FEE_BPS};
+use crate::limits::{
+    add_deposit, add_redeem, check_deposit, check_redeem, read_limits, read_total_assets,
+    read_user_deposited, read_user_redeemed, remove_assets, remove_deposit, remove_redeem,
+    write_limits,
+};
+use crate::math::mul_div_floor;
+use crate::offer::{load_offer, read_token_decimals, write_offer};
+use crate::operator::{
+    is_receiver, read_operator, remove_operator, remove_receiver, require_operator, write_operator,
+    write_receiver,
+};
+use crate::oracle::{
+    current_price, read_oracle, read_token_oracle, remove_oracle, remove_token_oracle,
+    write_oracle, write_token_oracle, Asset,
+};
+use crate::ownership::{accept_pending, read_pending, remove_pending, write_pending};
+use crate::pause::{is_paused, require_not_paused, write_paused};
+use crate::price::{assets_to_shares, check_price, shares_to_assets};
+use crate::redeem_request::{
+    delete_redeem_request, extend_redeem_requests, read_epoch_requester_count,
+    read_epoch_requesters, read_redeem_request, read_request_epochs, write_redeem_request,
+};
+use crate::reserve::{read_reserved, read_surplus, reserve, unreserve};
+use crate::roles::{
+    has_role, move_roles, remove_role, require_role, write_initial_roles, write_role, SELLER_ROLES,
+    TREASURY_ROLES,
+};
+use crate::share::{
+    escrow_approved_shares, escrow_shares, is_share_token, issue_shares, pay_out, release_shares,
+    retire_shares,
+};
+use crate::storage_types::{
+    BuyToken, ComplianceConfig, DataKey, DepositRequest, EpochDeposit, EpochFill, EpochInfo,
+    EpochSchedule, EpochWindow, Fees, Limits, Offer, Operation, OperatorScope, OracleConfig,
+    PendingTransfer, Price, RedeemRequest, Role, SettlementPreview, INSTANCE_BUMP_AMOUNT,
+    INSTANCE_LIFETIME_THRESHOLD,
+};
+use crate::upgrade::{
+    migrate_storage, read_legacy_offer, read_schema_version, write_schema_version, SCHEMA_VERSION,
+};
 use soroban_sdk::{
-    contract, contractimpl, panic_with_error, token, unwrap::UnwrapOptimized, Address, Env, Symbol,
+    contract, contractimpl, panic_with_error,
+    token::{self, TokenInterface},
+    Address, BytesN, Env, Map, String, Symbol, Vec,
 };
+use soroban_token_sdk::metadata::TokenMetadata;
+use soroban_token_sdk::TokenUtils;
 
 #[contract]
 pub struct Vault;
 
+#[cfg(not(feature = "legacy-initialize"))]
+#[contractimpl]
+impl Vault {
+    // set up the vault with the offer at deploy time
+    pub fn __constructor(
+        e: Env,
+        admin: Address,
+        seller: Address,
+        treasury: Address,
+        sell_token: Address,
+        buy_token: Address,
+        initial_price: Price,
+    ) -> Result<(), Error> {
+        initialize_vault(
+            &e,
+            admin,
+            seller,
+            treasury,
+            sell_token,
+            buy_token,
+            initial_price,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "legacy-initialize")]
 #[contractimpl]
 impl Vault {
-    // initialize the vault with the offer
+    // initialize the vault with the offer after deployment, only for legacy deploy scripts
+    // as the first caller becomes the seller
     pub fn initialize(
         e: Env,
         seller: Address,
         treasury: Address,
         sell_token: Address,
         buy_token: Address,
-        initial_price: u32,
-    ) {
+        initial_price: Price,
+    ) -> Result<(), Error> {
         if e.storage().instance().has(&DataKey::Offer) {
-            panic_with_error!(&e, Error::OfferAlreadyCreated);
-        }
-
-        if initial_price == 0 {
-            panic_with_error!(&e, Error::ZeroPrice);
+            return Err(Error::OfferAlreadyCreated);
         }
-
         seller.require_auth();
-        write_offer(
+        initialize_vault(
             &e,
-            &Offer {
-                seller: seller.clone(),
-                treasury: treasury.clone(),
-                sell_token: sell_token.clone(),
-                buy_token: buy_token.clone(),
-                price: initial_price,
-            },
-        );
-        e.storage().instance().set(&DataKey::TotalRedeem, &0_i128);
-        e.storage().instance().set(&DataKey::EpochId, &1_u32);
-        e.events().publish(
-            (
-                Symbol::new(&e, "vault_initialized"),
-                seller,
-                treasury,
-                sell_token,
-                buy_token,
-            ),
+            seller.clone(),
+            seller,
+            treasury,
+            sell_token,
+            buy_token,
             initial_price,
-        );
+        )?;
+        Ok(())
     }
+}
 
-    // deposit the buy token and receive the sell token
-    pub fn deposit(e: Env, buyer: Address, buy_token_amount: i128, min_sell_token_amount: i128) {
+#[contractimpl]
+impl Vault {
+    // deposit one of the accepted buy tokens and receive the sell token
+    pub fn deposit(
+        e: Env,
+        buyer: Address,
+        token: Address,
+        buy_token_amount: i128,
+        min_sell_token_amount: i128,
+    ) -> Result<(), Error> {
         buyer.require_auth();
+        require_not_paused(&e, Operation::Deposit)?;
+        require_compliant(&e, &buyer)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
 
-        let offer = load_offer(&e);
-        let sell_token_client = token::Client::new(&e, &offer.sell_token);
-        let buy_token_client = token::Client::new(&e, &offer.buy_token);
+        let offer = load_offer(&e)?;
+        let market = load_open_market(&e, &token)?;
+        let buy_token_client = token::Client::new(&e, &token);
 
         // Compute the amount of token that buyer needs to receive.
-        let sell_token_amount = buy_token_amount
-            .checked_mul(offer.price as i128)
-            .unwrap_optimized()
-            / 1000000 as i128;
+        let fees = read_fees(&e)?;
+        let (sell_token_amount, fee) = quote_deposit(&e, &market, buy_token_amount)?;
+        let net_amount = buy_token_amount.checked_sub(fee).ok_or(Error::Overflow)?;
 
         if sell_token_amount < min_sell_token_amount {
-            panic_with_error!(&e, Error::PriceTooLow);
+            return Err(Error::PriceTooLow);
         }
+        add_deposit(
+            &e,
+            &buyer,
+            base_value(&e, &offer, &market, buy_token_amount)?,
+        )?;
 
         let contract = e.current_contract_address();
         buy_token_client.transfer(&buyer, &contract, &buy_token_amount);
-        sell_token_client.transfer(&contract, &buyer, &sell_token_amount);
-        buy_token_client.transfer(&contract, &offer.treasury, &buy_token_amount);
+        issue_shares(&e, &market, &buyer, sell_token_amount)?;
+        buy_token_client.transfer(&contract, &market.treasury, &net_amount);
+        if fee > 0 {
+            buy_token_client.transfer(&contract, &fees.recipient, &fee);
+        }
 
         e.events().publish(
-            (Symbol::new(&e, "vault_deposit"), buyer, offer.treasury),
-            (buy_token_amount, sell_token_amount),
+            (
+                Symbol::new(&e, "vault_deposit"),
+                buyer,
+                market.treasury,
+                token,
+            ),
+            (buy_token_amount, sell_token_amount, fee),
         );
+        Ok(())
     }
 
     // claim the leftover that remain in the vault
-    pub fn claim_leftover(e: Env, token: Address, amount: i128) {
-        let offer = load_offer(&e);
-        offer.seller.require_auth();
-        token::Client::new(&e, &token).transfer(
-            &e.current_contract_address(),
-            &offer.seller,
-            &amount,
-        );
+    pub fn claim_leftover(
+        e: Env,
+        caller: Address,
+        token: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Sweeper, &caller)?;
+        let offer = load_offer(&e)?;
+        // escrowed and owed tokens stay in the vault
+        if amount > read_surplus(&e, &token) {
+            return Err(Error::InsufficientSurplus);
+        }
+        pay_out(&e, &token, &offer.seller, amount)?;
 
         e.events().publish(
             (Symbol::new(&e, "vault_claim_leftover"), offer.seller),
             (token, amount),
         );
+        Ok(())
     }
 
-    // update the price of the sell token
-    pub fn updt_price(e: Env, new_price: u32) {
-        if new_price == 0 {
-            panic_with_error!(&e, Error::ZeroPrice);
+    // send the rounding dust left by claims paid in `token` to the seller
+    pub fn sweep_dust(e: Env, caller: Address, token: Address) -> Result<(), Error> {
+        require_role(&e, Role::Sweeper, &caller)?;
+        let market = load_market(&e, &token)?;
+        let amount = take_dust(&e, &token);
+        if amount <= 0 {
+            return Err(Error::NoDust);
         }
-        let mut offer = load_offer(&e);
-        offer.seller.require_auth();
-        offer.price = new_price;
+        unreserve(&e, &token, amount);
+        pay_out(&e, &token, &market.seller, amount)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_sweep_dust"), market.seller, token),
+            (caller, amount),
+        );
+        Ok(())
+    }
+
+    // update the price of the sell token
+    pub fn updt_price(e: Env, caller: Address, new_price: Price) -> Result<(), Error> {
+        check_price(&new_price)?;
+        require_role(&e, Role::PriceUpdater, &caller)?;
+        let mut offer = load_offer(&e)?;
+        offer.price = new_price.clone();
         write_offer(&e, &offer);
 
+        e.events()
+            .publish((Symbol::new(&e, "vault_update_price"), caller), new_price);
+        Ok(())
+    }
+
+    // accept another buy token at its own price, its deposits going to `treasury` and its
+    // redemptions funded from it
+    pub fn add_buy_token(
+        e: Env,
+        caller: Address,
+        token: Address,
+        price: Price,
+        treasury: Address,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        check_price(&price)?;
+        if token == load_offer(&e)?.buy_token || read_buy_token(&e, &token).is_some() {
+            return Err(Error::TokenAlreadyAccepted);
+        }
+        let decimals = token::Client::new(&e, &token).decimals();
+        write_buy_token(
+            &e,
+            &token,
+            &BuyToken {
+                price: price.clone(),
+                decimals,
+                treasury: treasury.clone(),
+                enabled: true,
+            },
+        );
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_buy_token_added"), token),
+            (caller, treasury, price),
+        );
+        Ok(())
+    }
+
+    // update the price of the sell token in an added buy token
+    pub fn set_buy_token_price(
+        e: Env,
+        caller: Address,
+        token: Address,
+        new_price: Price,
+    ) -> Result<(), Error> {
+        check_price(&new_price)?;
+        require_role(&e, Role::PriceUpdater, &caller)?;
+        let mut buy_token = read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        buy_token.price = new_price.clone();
+        write_buy_token(&e, &token, &buy_token);
+
         e.events().publish(
-            (Symbol::new(&e, "vault_update_price"), offer.seller),
+            (Symbol::new(&e, "vault_update_price"), caller, token),
             new_price,
         );
+        Ok(())
     }
 
-    // request to redeem the shares
-    // the shares will be transferred to the vault
-    pub fn redeem_request(e: Env, sender: Address, amount: i128) {
-        sender.require_auth();
-        let epoch_id = Vault::get_epoch_id(e.clone());
-        let prev_redeem_request = read_redeem_request(&e, sender.clone());
-        if prev_redeem_request.shares_amount > 0 && prev_redeem_request.epoch_id < epoch_id {
-            Vault::claim_request(e.clone(), sender.clone());
+    // route the deposits and redemptions of an added buy token through another treasury
+    pub fn set_buy_token_treasury(
+        e: Env,
+        caller: Address,
+        token: Address,
+        treasury: Address,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut buy_token = read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        buy_token.treasury = treasury.clone();
+        write_buy_token(&e, &token, &buy_token);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_buy_token_treasury_set"), token),
+            (caller, treasury),
+        );
+        Ok(())
+    }
+
+    // price an added buy token from a SEP-40 price feed, or from its stored price with `None`
+    pub fn set_buy_token_oracle(
+        e: Env,
+        caller: Address,
+        token: Address,
+        oracle: Option<OracleConfig>,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        match &oracle {
+            Some(config) => write_token_oracle(&e, &token, config),
+            None => remove_token_oracle(&e, &token),
         }
-        let total_redeem: i128 = Vault::get_total_redeem(e.clone());
 
-        let new_total_redeem = total_redeem.checked_add(amount).unwrap_optimized();
-        e.storage()
-            .instance()
-            .set(&DataKey::TotalRedeem, &new_total_redeem);
+        e.events().publish(
+            (Symbol::new(&e, "vault_buy_token_oracle_set"), token),
+            (caller, oracle.map(|config| config.oracle)),
+        );
+        Ok(())
+    }
 
-        let new_redeem_amount = prev_redeem_request
-            .shares_amount
-            .checked_add(amount)
-            .unwrap_optimized();
-        write_redeem_request(&e, sender.clone(), new_redeem_amount, epoch_id);
+    // stop taking deposits and redeem requests in an added buy token, pending requests can
+    // still be settled, claimed and cancelled
+    pub fn disable_buy_token(e: Env, caller: Address, token: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut buy_token = read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        buy_token.enabled = false;
+        write_buy_token(&e, &token, &buy_token);
 
-        let offer = load_offer(&e);
-        let sell_token_client = token::Client::new(&e, &offer.sell_token);
-        let contract_address = e.current_contract_address();
-        sell_token_client.transfer(&sender, &contract_address, &amount);
+        e.events()
+            .publish((Symbol::new(&e, "vault_buy_token_disabled"), token), caller);
+        Ok(())
+    }
+
+    pub fn enable_buy_token(e: Env, caller: Address, token: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut buy_token = read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        buy_token.enabled = true;
+        write_buy_token(&e, &token, &buy_token);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_buy_token_enabled"), token), caller);
+        Ok(())
+    }
+
+    // time the current and following epochs, starting the current one at `start`
+    pub fn set_schedule(
+        e: Env,
+        caller: Address,
+        start: u64,
+        period: u64,
+        cutoff_offset: u64,
+        settlement_offset: u64,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        if period == 0 || cutoff_offset > settlement_offset {
+            return Err(Error::InvalidSchedule);
+        }
+        let schedule = EpochSchedule {
+            period,
+            cutoff_offset,
+            settlement_offset,
+        };
+        write_schedule(&e, &schedule);
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        write_epoch_start(&e, epoch_id, start);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_schedule_set"), caller, epoch_id),
+            (start, period, cutoff_offset, settlement_offset),
+        );
+        Ok(())
+    }
+
+    // name the shares issued by the vault, before the first one is issued
+    pub fn set_share_metadata(
+        e: Env,
+        caller: Address,
+        decimal: u32,
+        name: String,
+        symbol: String,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let offer = load_offer(&e)?;
+        if !is_share_token(&e, &offer) {
+            return Err(Error::NotShareToken);
+        }
+        if read_total_supply(&e) > 0 {
+            return Err(Error::SharesAlreadyIssued);
+        }
+        let mut offer = offer;
+        offer.sell_decimals = decimal;
+        write_offer(&e, &offer);
+        TokenUtils::new(&e).metadata().set_metadata(&TokenMetadata {
+            decimal,
+            name: name.clone(),
+            symbol: symbol.clone(),
+        });
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_share_metadata_set"), caller),
+            (decimal, name, symbol),
+        );
+        Ok(())
+    }
+
+    // set the entry fee charged on deposits and the exit fee charged on claims
+    pub fn set_fees(
+        e: Env,
+        caller: Address,
+        entry_fee_bps: u32,
+        exit_fee_bps: u32,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        if entry_fee_bps > MAX_FEE_BPS || exit_fee_bps > MAX_FEE_BPS {
+            return Err(Error::FeeTooHigh);
+        }
+        let mut fees = read_fees(&e)?;
+        fees.entry_fee_bps = entry_fee_bps;
+        fees.exit_fee_bps = exit_fee_bps;
+        write_fees(&e, &fees);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_fees_updated"), caller),
+            (entry_fee_bps, exit_fee_bps),
+        );
+        Ok(())
+    }
+
+    // set the address collecting the fees
+    pub fn set_fee_recipient(e: Env, caller: Address, recipient: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut fees = read_fees(&e)?;
+        fees.recipient = recipient.clone();
+        write_fees(&e, &fees);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_fee_recipient_updated"), caller),
+            recipient,
+        );
+        Ok(())
+    }
+
+    // bound the buy token amount of a single deposit, a maximum of 0 being no limit
+    pub fn set_deposit_limits(
+        e: Env,
+        caller: Address,
+        min_amount: i128,
+        max_amount: i128,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut limits = read_limits(&e);
+        limits.min_deposit = min_amount;
+        limits.max_deposit = max_amount;
+        write_limits(&e, &limits)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_deposit_limits_set"), caller),
+            (min_amount, max_amount),
+        );
+        Ok(())
+    }
+
+    // bound the share amount of a single redeem request, a maximum of 0 being no limit
+    pub fn set_redeem_limits(
+        e: Env,
+        caller: Address,
+        min_amount: i128,
+        max_amount: i128,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut limits = read_limits(&e);
+        limits.min_redeem = min_amount;
+        limits.max_redeem = max_amount;
+        write_limits(&e, &limits)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_redeem_limits_set"), caller),
+            (min_amount, max_amount),
+        );
+        Ok(())
+    }
+
+    // bound what a single user can deposit and request to redeem in total
+    pub fn set_user_caps(
+        e: Env,
+        caller: Address,
+        deposit_cap: i128,
+        redeem_cap: i128,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut limits = read_limits(&e);
+        limits.user_deposit_cap = deposit_cap;
+        limits.user_redeem_cap = redeem_cap;
+        write_limits(&e, &limits)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_user_caps_set"), caller),
+            (deposit_cap, redeem_cap),
+        );
+        Ok(())
+    }
+
+    // bound the shares requested to redeem in an epoch
+    pub fn set_epoch_redeem_cap(e: Env, caller: Address, cap: i128) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut limits = read_limits(&e);
+        limits.epoch_redeem_cap = cap;
+        write_limits(&e, &limits)?;
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_epoch_redeem_cap_set"), caller), cap);
+        Ok(())
+    }
+
+    // bound the buy tokens held by the vault
+    pub fn set_tvl_cap(e: Env, caller: Address, cap: i128) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let mut limits = read_limits(&e);
+        limits.tvl_cap = cap;
+        write_limits(&e, &limits)?;
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_tvl_cap_set"), caller), cap);
+        Ok(())
+    }
+
+    // price deposits and settlements from a SEP-40 price feed instead of the stored price
+    pub fn set_oracle(
+        e: Env,
+        caller: Address,
+        oracle: Address,
+        asset: Asset,
+        max_age: u64,
+        max_deviation_bps: u32,
+        allow_fallback: bool,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let config = OracleConfig {
+            oracle,
+            asset,
+            max_age,
+            max_deviation_bps,
+            allow_fallback,
+        };
+        write_oracle(&e, &config);
 
         e.events().publish(
-            (Symbol::new(&e, "vault_redeem_request"), sender),
-            (amount, new_redeem_amount, new_redeem_amount),
+            (Symbol::new(&e, "vault_oracle_set"), caller, config.oracle),
+            (
+                config.max_age,
+                config.max_deviation_bps,
+                config.allow_fallback,
+            ),
         );
+        Ok(())
+    }
+
+    // go back to the stored price
+    pub fn remove_oracle(e: Env, caller: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        remove_oracle(&e);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_oracle_removed"), caller), ());
+        Ok(())
+    }
+
+    // request to redeem the shares for one of the accepted buy tokens
+    // the shares will be transferred to the vault
+    pub fn redeem_request(
+        e: Env,
+        sender: Address,
+        token: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        sender.require_auth();
+        submit_redeem_request(&e, &sender, &token, amount, false)
     }
 
-    // cancel the redeem request
+    // cancel the sender's redeem requests that are not settled yet
     // the shares will be transferred back to the sender
-    pub fn cancel_request(e: Env, sender: Address) {
+    pub fn cancel_request(e: Env, sender: Address) -> Result<(), Error> {
         sender.require_auth();
-        let redeem_request = read_redeem_request(&e, sender.clone());
-        if redeem_request.shares_amount <= 0 {
-            panic_with_error!(&e, Error::NoRedeemRequest);
-        }
-        let total_redeem: i128 = Vault::get_total_redeem(e.clone());
-        let new_total_redeem = total_redeem
-            .checked_sub(redeem_request.shares_amount)
-            .unwrap_optimized();
+        cancel_redeem_requests(&e, &sender)
+    }
 
+    // cancel the sender's redeem request of the epoch paid in `token`
+    pub fn cancel_epoch_request(
+        e: Env,
+        sender: Address,
+        token: Address,
+        epoch_id: u32,
+    ) -> Result<(), Error> {
+        sender.require_auth();
+        require_not_denied(&e, &sender)?;
         e.storage()
             .instance()
-            .set(&DataKey::TotalRedeem, &new_total_redeem);
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        cancel_redeem_request(&e, sender, token, epoch_id)?;
+        Ok(())
+    }
 
-        let offer = load_offer(&e);
-        let sell_token_client = token::Client::new(&e, &offer.sell_token);
-        let contract_address = e.current_contract_address();
-        sell_token_client.transfer(&contract_address, &sender, &redeem_request.shares_amount);
+    // claim every redeem request of the sender paid in `token` whose epoch is setled
+    pub fn claim_request(e: Env, sender: Address, token: Address) -> Result<(), Error> {
+        sender.require_auth();
+        claim_redeem_requests(&e, &sender, &token, &sender)
+    }
+
+    // claim the sender's redeem request of a setled epoch paid in `token`
+    pub fn claim_epoch_request(
+        e: Env,
+        sender: Address,
+        token: Address,
+        epoch_id: u32,
+    ) -> Result<(), Error> {
+        sender.require_auth();
+        require_not_paused(&e, Operation::Claim)?;
+        require_not_denied(&e, &sender)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        claim_redeem_request(&e, sender.clone(), token, epoch_id, &sender)?;
+        Ok(())
+    }
+
+    // let an operator, e.g. a custodian or smart wallet, act for the owner in the given scopes
+    // until `expires_at`, 0 for no expiry
+    pub fn approve_operator(
+        e: Env,
+        owner: Address,
+        operator: Address,
+        scopes: Vec<OperatorScope>,
+        expires_at: u64,
+    ) -> Result<(), Error> {
+        owner.require_auth();
+        for scope in scopes.iter() {
+            write_operator(&e, &owner, &operator, scope, expires_at)?;
+        }
 
-        let epoch_id = Vault::get_epoch_id(e.clone());
-        delete_redeem_request(&e, sender.clone(), epoch_id);
         e.events().publish(
-            (Symbol::new(&e, "vault_cancel_request"), sender),
-            (redeem_request.shares_amount, new_total_redeem),
+            (Symbol::new(&e, "vault_operator_approved"), owner, operator),
+            (scopes, expires_at),
         );
+        Ok(())
     }
 
-    // claim the redeem request after the epoch is setled
-    pub fn claim_request(e: Env, sender: Address) {
-        sender.require_auth();
-        let redeem_request = read_redeem_request(&e, sender.clone());
-        let epoch_id: u32 = Vault::get_epoch_id(e.clone());
-        if epoch_id <= redeem_request.epoch_id {
-            panic_with_error!(&e, Error::EpochNotSetled);
+    pub fn revoke_operator(
+        e: Env,
+        owner: Address,
+        operator: Address,
+        scopes: Vec<OperatorScope>,
+    ) -> Result<(), Error> {
+        owner.require_auth();
+        for scope in scopes.iter() {
+            remove_operator(&e, &owner, &operator, scope);
         }
 
-        if redeem_request.shares_amount <= 0 {
-            panic_with_error!(&e, Error::NoRedeemRequest);
-        }
-        let redeem_rate: u32 = Vault::get_redeem_rate(e.clone(), redeem_request.epoch_id);
-        let offer = load_offer(&e);
-        let buy_token_amount = redeem_request
-            .shares_amount
-            .checked_mul(redeem_rate as i128)
-            .unwrap_optimized()
-            / 1000000 as i128;
+        e.events().publish(
+            (Symbol::new(&e, "vault_operator_revoked"), owner, operator),
+            scopes,
+        );
+        Ok(())
+    }
 
-        let buy_token_client = token::Client::new(&e, &offer.buy_token);
-        let contract_address = e.current_contract_address();
+    // let operators pay the owner's claims to `receiver`
+    pub fn approve_receiver(e: Env, owner: Address, receiver: Address) -> Result<(), Error> {
+        owner.require_auth();
+        write_receiver(&e, &owner, &receiver);
 
-        buy_token_client.transfer(&contract_address, &sender, &buy_token_amount);
+        e.events().publish(
+            (Symbol::new(&e, "vault_receiver_approved"), owner, receiver),
+            (),
+        );
+        Ok(())
+    }
 
-        delete_redeem_request(&e, sender.clone(), epoch_id);
+    pub fn revoke_receiver(e: Env, owner: Address, receiver: Address) -> Result<(), Error> {
+        owner.require_auth();
+        remove_receiver(&e, &owner, &receiver);
 
         e.events().publish(
-            (Symbol::new(&e, "vault_claim_request"), sender),
-            (redeem_request.shares_amount, buy_token_amount),
+            (Symbol::new(&e, "vault_receiver_revoked"), owner, receiver),
+            (),
         );
+        Ok(())
     }
 
-    // setle the epoch and transfer the buy token to the treasury
-    pub fn setle_epoch(e: Env) {
-        let offer = load_offer(&e);
-        offer.treasury.require_auth();
-        let epoch_id = Vault::get_epoch_id(e.clone());
-        let total_redeem: i128 = Vault::get_total_redeem(e.clone());
-        let total_asset = &total_redeem
-            .checked_mul(offer.price as i128)
-            .unwrap_optimized()
-            / 1000000 as i128;
+    // request to redeem the owner's shares as its operator. A pre-minted sell token is taken
+    // through the owner's allowance to the vault.
+    pub fn redeem_request_for(
+        e: Env,
+        operator: Address,
+        owner: Address,
+        token: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        require_operator(&e, &owner, &operator, OperatorScope::Redeem)?;
+        require_not_denied(&e, &operator)?;
+        submit_redeem_request(&e, &owner, &token, amount, true)?;
+        publish_operator_action(&e, &operator, &owner, "redeem_request", &owner);
+        Ok(())
+    }
+
+    // cancel the owner's redeem requests that are not settled yet as its operator, the shares
+    // go back to the owner
+    pub fn cancel_request_for(e: Env, operator: Address, owner: Address) -> Result<(), Error> {
+        require_operator(&e, &owner, &operator, OperatorScope::Cancel)?;
+        require_not_denied(&e, &operator)?;
+        cancel_redeem_requests(&e, &owner)?;
+        publish_operator_action(&e, &operator, &owner, "cancel_request", &owner);
+        Ok(())
+    }
+
+    // claim the owner's settled redeem requests paid in `token` as its operator, paying the
+    // owner or a receiver the owner approved
+    pub fn claim_request_for(
+        e: Env,
+        operator: Address,
+        owner: Address,
+        token: Address,
+        receiver: Address,
+    ) -> Result<(), Error> {
+        require_operator(&e, &owner, &operator, OperatorScope::Claim)?;
+        require_not_denied(&e, &operator)?;
+        require_not_denied(&e, &receiver)?;
+        if !is_receiver(&e, &owner, &receiver) {
+            return Err(Error::Unauthorized);
+        }
+        claim_redeem_requests(&e, &owner, &token, &receiver)?;
+        publish_operator_action(&e, &operator, &owner, "claim_request", &receiver);
+        Ok(())
+    }
 
+    // pay out the settled redeem requests in `token` of many users at once, skipping users
+    // without a claimable request. Returns the users skipped.
+    pub fn process_claims(
+        e: Env,
+        caller: Address,
+        token: Address,
+        users: Vec<Address>,
+    ) -> Result<Vec<Address>, Error> {
+        require_role(&e, Role::Settler, &caller)?;
+        require_not_paused(&e, Operation::Claim)?;
         e.storage()
             .instance()
-            .set(&DataKey::RedeemRate(epoch_id), &offer.price);
-        let new_epoch_id = epoch_id.checked_add(1).unwrap_optimized();
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        load_market(&e, &token)?;
+        let mut skipped = Vec::new(&e);
+        for user in users.iter() {
+            // denylisted users cannot be paid, they are skipped like users with nothing to claim
+            match claim_redeem_requests(&e, &user, &token, &user) {
+                Ok(()) => {}
+                Err(Error::NoRedeemRequest) | Err(Error::Denylisted) => skipped.push_back(user),
+                Err(error) => return Err(error),
+            }
+        }
 
-        e.storage().instance().set(&DataKey::EpochId, &new_epoch_id);
-        e.storage().instance().set(&DataKey::TotalRedeem, &0_i128);
+        e.events().publish(
+            (Symbol::new(&e, "vault_process_claims"), caller, token),
+            (users.len() - skipped.len(), skipped.clone()),
+        );
+        Ok(skipped)
+    }
+
+    // request to deposit buy tokens at the price the epoch is settled at
+    // the buy tokens will be transferred to the vault
+    pub fn deposit_request(e: Env, sender: Address, amount: i128) -> Result<(), Error> {
+        sender.require_auth();
+        if amount < 0 {
+            return Err(Error::NegativeAmount);
+        }
+        require_not_paused(&e, Operation::Deposit)?;
+        require_compliant(&e, &sender)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        let request_epoch_id = request_epoch_id(&e, epoch_id)?;
+        let mut prev_deposit_request = read_deposit_request(&e, sender.clone());
+        if prev_deposit_request.assets_amount > 0 && prev_deposit_request.epoch_id < epoch_id {
+            require_not_paused(&e, Operation::Claim)?;
+            claim_deposit_request(&e, sender.clone())?;
+            prev_deposit_request = read_deposit_request(&e, sender.clone());
+        }
+        if prev_deposit_request.assets_amount > 0
+            && prev_deposit_request.epoch_id != request_epoch_id
+        {
+            return Err(Error::RequestInOtherEpoch);
+        }
+
+        let total_key = total_deposit_key(epoch_id, request_epoch_id);
+        let total_deposit: i128 = e.storage().instance().get(&total_key).unwrap_or(0);
+        let new_total_deposit = total_deposit.checked_add(amount).ok_or(Error::Overflow)?;
+        e.storage().instance().set(&total_key, &new_total_deposit);
+        add_deposit(&e, &sender, amount)?;
 
+        let new_deposit_amount = prev_deposit_request
+            .assets_amount
+            .checked_add(amount)
+            .ok_or(Error::Overflow)?;
+        write_deposit_request(&e, sender.clone(), new_deposit_amount, request_epoch_id)?;
+
+        let offer = load_offer(&e)?;
         let buy_token_client = token::Client::new(&e, &offer.buy_token);
-        let sell_token_client = token::Client::new(&e, &offer.sell_token);
         let contract_address = e.current_contract_address();
-
-        sell_token_client.transfer(&contract_address, &offer.seller, &total_redeem);
-        buy_token_client.transfer(&offer.treasury, &contract_address, &total_asset);
+        buy_token_client.transfer(&sender, &contract_address, &amount);
+        reserve(&e, &offer.buy_token, amount)?;
 
         e.events().publish(
-            (Symbol::new(&e, "vault_setle_epoch"), offer.treasury),
-            (epoch_id, total_redeem, total_asset, offer.price),
+            (Symbol::new(&e, "vault_deposit_request"), sender),
+            (
+                amount,
+                new_deposit_amount,
+                new_total_deposit,
+                request_epoch_id,
+            ),
         );
+        Ok(())
     }
 
-    // read functions
+    // cancel the deposit request
+    // the buy tokens will be transferred back to the sender
+    pub fn cancel_deposit(e: Env, sender: Address) -> Result<(), Error> {
+        sender.require_auth();
+        require_not_denied(&e, &sender)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        let deposit_request = read_deposit_request(&e, sender.clone());
+        if deposit_request.assets_amount <= 0 {
+            return Err(Error::NoDepositRequest);
+        }
+        // buy tokens of settled requests were already sent to the treasury
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        if deposit_request.epoch_id < epoch_id {
+            return Err(Error::InvalidEpochId);
+        }
+        let total_key = total_deposit_key(epoch_id, deposit_request.epoch_id);
+        let total_deposit: i128 = e.storage().instance().get(&total_key).unwrap_or(0);
+        let new_total_deposit = total_deposit
+            .checked_sub(deposit_request.assets_amount)
+            .ok_or(Error::Overflow)?;
+        e.storage().instance().set(&total_key, &new_total_deposit);
+        remove_deposit(&e, &sender, deposit_request.assets_amount)?;
 
-    pub fn get_offer(e: Env) -> Offer {
-        load_offer(&e)
+        let offer = load_offer(&e)?;
+        let buy_token_client = token::Client::new(&e, &offer.buy_token);
+        let contract_address = e.current_contract_address();
+        unreserve(&e, &offer.buy_token, deposit_request.assets_amount);
+        buy_token_client.transfer(&contract_address, &sender, &deposit_request.assets_amount);
+
+        delete_deposit_request(&e, sender.clone());
+        e.events().publish(
+            (Symbol::new(&e, "vault_cancel_deposit"), sender),
+            (deposit_request.assets_amount, new_total_deposit),
+        );
+        Ok(())
     }
 
-    pub fn get_request(e: Env, sender: Address) -> RedeemRequest {
-        read_redeem_request(&e, sender.clone())
+    // claim the shares of the deposit request after the epoch is setled
+    pub fn claim_deposit(e: Env, sender: Address) -> Result<(), Error> {
+        sender.require_auth();
+        require_not_paused(&e, Operation::Claim)?;
+        require_not_denied(&e, &sender)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        claim_deposit_request(&e, sender)?;
+        Ok(())
     }
 
-    pub fn get_epoch_id(e: Env) -> u32 {
-        if let Some(epoch_id) = e.storage().instance().get::<_, u32>(&DataKey::EpochId) {
-            epoch_id
-        } else {
-            panic_with_error!(&e, Error::OfferNotCreated);
+    // setle the epoch and transfer the buy token to the treasury
+    pub fn setle_epoch(e: Env, caller: Address) -> Result<(), Error> {
+        require_role(&e, Role::Settler, &caller)?;
+        require_not_paused(&e, Operation::Settlement)?;
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        if !can_settle(&e, epoch_id) {
+            return Err(Error::SettlementTooEarly);
         }
-    }
+        let offer = load_offer(&e)?;
+        let quote = quote_settlement(&e, &offer, epoch_id)?;
 
-    pub fn get_total_redeem(e: Env) -> i128 {
-        if let Some(total_redeem) = e.storage().instance().get::<_, i128>(&DataKey::TotalRedeem) {
-            total_redeem
-        } else {
-            panic_with_error!(&e, Error::OfferNotCreated);
+        // deposits are settled first, so their buy tokens can fund the redemptions
+        setle_deposits(&e, &offer, &quote)?;
+        setle_redemptions(&e, &offer, &quote)?;
+        remove_assets(&e, quote.funded_asset)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_setle_epoch"), caller.clone()),
+            (
+                epoch_id,
+                quote.total_redeem,
+                quote.filled_shares,
+                quote.funded_asset,
+                quote.price.clone(),
+                quote.fill_ratio,
+            ),
+        );
+
+        // redemptions paid in the other tokens are funded by their own treasuries, tokens
+        // without requests are not priced
+        for token in read_buy_tokens(&e).iter() {
+            if read_total_redeem(&e, &token) == 0 {
+                roll_total_redeem(&e, &token, 0)?;
+                continue;
+            }
+            let market = load_market(&e, &token)?;
+            let token_quote = quote_settlement(&e, &market, epoch_id)?;
+            setle_redemptions(&e, &market, &token_quote)?;
+            remove_assets(
+                &e,
+                shares_to_assets(&e, &offer, &quote.price, token_quote.filled_shares)?,
+            )?;
+
+            e.events().publish(
+                (Symbol::new(&e, "vault_setle_token"), caller.clone(), token),
+                (
+                    epoch_id,
+                    token_quote.total_redeem,
+                    token_quote.filled_shares,
+                    token_quote.funded_asset,
+                    token_quote.price,
+                    token_quote.fill_ratio,
+                ),
+            );
         }
+
+        let new_epoch_id = epoch_id.checked_add(1).ok_or(Error::Overflow)?;
+        e.storage().instance().set(&DataKey::EpochId, &new_epoch_id);
+        start_next_epoch(&e, epoch_id)?;
+        Ok(())
     }
 
-    pub fn get_redeem_rate(e: Env, epoch_id: u32) -> u32 {
-        if let Some(rate) = e
-            .storage()
+    // keep the vault, the given users' requests and the given epochs' rates alive
+    pub fn extend_ttl(e: Env, users: Vec<Address>, epoch_ids: Vec<u32>) -> Result<(), Error> {
+        e.storage()
             .instance()
-            .get::<_, u32>(&DataKey::RedeemRate(epoch_id))
-        {
-            rate
-        } else {
-            panic_with_error!(&e, Error::OfferNotCreated);
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        let tokens = accepted_tokens(&e, &load_offer(&e)?);
+        for user in users.iter() {
+            for token in tokens.iter() {
+                extend_redeem_requests(&e, user.clone(), &token);
+            }
+            extend_deposit_request(&e, user);
         }
+        for epoch_id in epoch_ids.iter() {
+            for token in tokens.iter() {
+                extend_redeem_rate(&e, &token, epoch_id);
+                extend_epoch_fill(&e, &token, epoch_id);
+                extend_epoch_info(&e, &token, epoch_id);
+            }
+            extend_epoch_deposit(&e, epoch_id);
+        }
+        Ok(())
+    }
+
+    // halt an operation until it is unpaused
+    pub fn pause(e: Env, caller: Address, operation: Operation) -> Result<(), Error> {
+        require_role(&e, Role::Pauser, &caller)?;
+        write_paused(&e, operation, true);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_paused"), operation), caller);
+        Ok(())
     }
+
+    // resume a paused operation
+    pub fn unpause(e: Env, caller: Address, operation: Operation) -> Result<(), Error> {
+        require_role(&e, Role::Pauser, &caller)?;
+        write_paused(&e, operation, false);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_unpaused"), operation), caller);
+        Ok(())
+    }
+
+    // require an allowlist entry or a registry verification to open positions
+    pub fn set_compliance(
+        e: Env,
+        caller: Address,
+        enforced: bool,
+        registry: Option<Address>,
+    ) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        write_compliance(
+            &e,
+            &ComplianceConfig {
+                enforced,
+                registry: registry.clone(),
+            },
+        );
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_compliance_set"), caller),
+            (enforced, registry),
+        );
+        Ok(())
+    }
+
+    // add an account to the allowlist until `expires_at`, 0 for no expiry
+    pub fn allow(e: Env, caller: Address, account: Address, expires_at: u64) -> Result<(), Error> {
+        require_role(&e, Role::Compliance, &caller)?;
+        write_allowed(&e, &account, expires_at);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_allowed"), account),
+            (caller, expires_at),
+        );
+        Ok(())
+    }
+
+    // remove an account from the allowlist, its pending requests can still be claimed
+    pub fn disallow(e: Env, caller: Address, account: Address) -> Result<(), Error> {
+        require_role(&e, Role::Compliance, &caller)?;
+        remove_allowed(&e, &account);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_disallowed"), account), caller);
+        Ok(())
+    }
+
+    // block a sanctioned account from every operation
+    pub fn deny(e: Env, caller: Address, account: Address) -> Result<(), Error> {
+        require_role(&e, Role::Compliance, &caller)?;
+        write_denied(&e, &account, true);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_denied"), account), caller);
+        Ok(())
+    }
+
+    pub fn undeny(e: Env, caller: Address, account: Address) -> Result<(), Error> {
+        require_role(&e, Role::Compliance, &caller)?;
+        write_denied(&e, &account, false);
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_undenied"), account), caller);
+        Ok(())
+    }
+
+    // grant a role to an account, only callable by an admin
+    pub fn grant_role(e: Env, caller: Address, role: Role, account: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        if has_role(&e, role, &account) {
+            return Err(Error::RoleAlreadyGranted);
+        }
+        write_role(&e, role, &account);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_role_granted"), role, account),
+            caller,
+        );
+        Ok(())
+    }
+
+    // revoke a role from an account, only callable by an admin
+    pub fn revoke_role(e: Env, caller: Address, role: Role, account: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        if !has_role(&e, role, &account) {
+            return Err(Error::RoleNotGranted);
+        }
+        remove_role(&e, role, &account);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_role_revoked"), role, account),
+            caller,
+        );
+        Ok(())
+    }
+
+    // give up a role held by the account
+    pub fn renounce_role(e: Env, account: Address, role: Role) -> Result<(), Error> {
+        account.require_auth();
+        if !has_role(&e, role, &account) {
+            return Err(Error::RoleNotGranted);
+        }
+        remove_role(&e, role, &account);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_role_revoked"), role, account.clone()),
+            account,
+        );
+        Ok(())
+    }
+
+    // propose a new seller, the proposal has to be accepted by the new seller
+    pub fn propose_seller(e: Env, caller: Address, new_seller: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let pending = write_pending(&e, &DataKey::PendingSeller, new_seller.clone())?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_seller_proposed"), caller, new_seller),
+            pending.expires_at,
+        );
+        Ok(())
+    }
+
+    // accept the pending seller proposal
+    pub fn accept_seller(e: Env) -> Result<(), Error> {
+        let new_seller = accept_pending(&e, &DataKey::PendingSeller)?;
+        let mut offer = load_offer(&e)?;
+        let old_seller = offer.seller;
+        offer.seller = new_seller.clone();
+        write_offer(&e, &offer);
+        move_roles(&e, &SELLER_ROLES, &old_seller, &new_seller);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_seller_accepted"), old_seller),
+            new_seller,
+        );
+        Ok(())
+    }
+
+    // cancel the pending seller proposal
+    pub fn cancel_seller(e: Env, caller: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let pending = remove_pending(&e, &DataKey::PendingSeller)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_seller_cancelled"), caller),
+            pending.new_address,
+        );
+        Ok(())
+    }
+
+    // propose a new treasury, the proposal has to be accepted by the new treasury
+    pub fn propose_treasury(e: Env, caller: Address, new_treasury: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let pending = write_pending(&e, &DataKey::PendingTreasury, new_treasury.clone())?;
+
+        e.events().publish(
+            (
+                Symbol::new(&e, "vault_treasury_proposed"),
+                caller,
+                new_treasury,
+            ),
+            pending.expires_at,
+        );
+        Ok(())
+    }
+
+    // accept the pending treasury proposal
+    pub fn accept_treasury(e: Env) -> Result<(), Error> {
+        let new_treasury = accept_pending(&e, &DataKey::PendingTreasury)?;
+        let mut offer = load_offer(&e)?;
+        let old_treasury = offer.treasury;
+        offer.treasury = new_treasury.clone();
+        write_offer(&e, &offer);
+        move_roles(&e, &TREASURY_ROLES, &old_treasury, &new_treasury);
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_treasury_accepted"), old_treasury),
+            new_treasury,
+        );
+        Ok(())
+    }
+
+    // cancel the pending treasury proposal
+    pub fn cancel_treasury(e: Env, caller: Address) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        let pending = remove_pending(&e, &DataKey::PendingTreasury)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_treasury_cancelled"), caller),
+            pending.new_address,
+        );
+        Ok(())
+    }
+
+    // install new contract code, `migrate` has to be called afterwards
+    pub fn upgrade(e: Env, caller: Address, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        require_role(&e, Role::Admin, &caller)?;
+        e.deployer()
+            .update_current_contract_wasm(new_wasm_hash.clone());
+
+        e.events()
+            .publish((Symbol::new(&e, "vault_upgraded"), caller), new_wasm_hash);
+        Ok(())
+    }
+
+    // convert the storage written by older contract code to the current layout
+    pub fn migrate(e: Env, caller: Address) -> Result<(), Error> {
+        let version = read_schema_version(&e);
+        if version >= SCHEMA_VERSION {
+            return Err(Error::AlreadyMigrated);
+        }
+        if version == 1 {
+            // roles don't exist yet, the seller was the only privileged account
+            caller.require_auth();
+            if caller != read_legacy_offer(&e)?.seller {
+                return Err(Error::Unauthorized);
+            }
+        } else {
+            require_role(&e, Role::Admin, &caller)?;
+        }
+        migrate_storage(&e, version)?;
+
+        e.events().publish(
+            (Symbol::new(&e, "vault_migrated"), caller),
+            (version, SCHEMA_VERSION),
+        );
+        Ok(())
+    }
+
+    // read functions
+
+    pub fn has_role(e: Env, role: Role, account: Address) -> Result<bool, Error> {
+        Ok(has_role(&e, role, &account))
+    }
+
+    pub fn is_paused(e: Env, operation: Operation) -> Result<bool, Error> {
+        Ok(is_paused(&e, operation))
+    }
+
+    pub fn get_offer(e: Env) -> Result<Offer, Error> {
+        load_offer(&e)
+    }
+
+    pub fn get_fees(e: Env) -> Result<Fees, Error> {
+        read_fees(&e)
+    }
+
+    pub fn get_buy_tokens(e: Env) -> Result<Vec<Address>, Error> {
+        Ok(read_buy_tokens(&e))
+    }
+
+    pub fn get_buy_token(e: Env, token: Address) -> Result<BuyToken, Error> {
+        read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)
+    }
+
+    // shares bought and entry fee taken by a deposit of `buy_token_amount` of `token` at the
+    // current price, failing like the deposit would on the limits that do not depend on the buyer
+    pub fn preview_deposit(
+        e: Env,
+        token: Address,
+        buy_token_amount: i128,
+    ) -> Result<(i128, i128), Error> {
+        let market = load_open_market(&e, &token)?;
+        check_deposit(
+            &e,
+            base_value(&e, &load_offer(&e)?, &market, buy_token_amount)?,
+        )?;
+        quote_deposit(&e, &market, buy_token_amount)
+    }
+
+    // buy tokens paid and exit fee taken for `shares_amount` requested now in `token` and fully
+    // filled at the current price, failing like the request would on the limits that do not
+    // depend on the sender
+    pub fn preview_redeem(
+        e: Env,
+        token: Address,
+        shares_amount: i128,
+    ) -> Result<(i128, i128), Error> {
+        let market = load_open_market(&e, &token)?;
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        let epoch_total = requested_shares(&e, epoch_id, request_epoch_id(&e, epoch_id)?)?;
+        check_redeem(
+            &e,
+            shares_amount,
+            epoch_total
+                .checked_add(shares_amount)
+                .ok_or(Error::Overflow)?,
+        )?;
+        let price = current_price(&e, &market)?;
+        let gross_amount = shares_to_assets(&e, &market, &price, shares_amount)?;
+        let fee = fee_amount(&e, gross_amount, read_fees(&e)?.exit_fee_bps)?;
+        Ok((gross_amount.checked_sub(fee).ok_or(Error::Overflow)?, fee))
+    }
+
+    // filled shares, buy tokens paid and exit fee taken by claim_request for the user
+    pub fn preview_claim(
+        e: Env,
+        user: Address,
+        token: Address,
+    ) -> Result<(i128, i128, i128), Error> {
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        let market = load_market(&e, &token)?;
+        let exit_fee_bps = read_fees(&e)?.exit_fee_bps;
+        let mut fills = Map::new(&e);
+        let (mut filled_shares, mut buy_token_amount, mut fee) = (0_i128, 0_i128, 0_i128);
+        for request_epoch_id in read_request_epochs(&e, user.clone(), &token).iter() {
+            if request_epoch_id < epoch_id {
+                let redeem_request =
+                    read_redeem_request(&e, user.clone(), &token, request_epoch_id);
+                let (filled, _, gross_amount) = fill_redeem_request(
+                    &e,
+                    &market,
+                    &mut fills,
+                    request_epoch_id,
+                    redeem_request.shares_amount,
+                    epoch_id,
+                )?;
+                let request_fee = fee_amount(&e, gross_amount, exit_fee_bps)?;
+                filled_shares = filled_shares.checked_add(filled).ok_or(Error::Overflow)?;
+                buy_token_amount = gross_amount
+                    .checked_sub(request_fee)
+                    .and_then(|amount| buy_token_amount.checked_add(amount))
+                    .ok_or(Error::Overflow)?;
+                fee = fee.checked_add(request_fee).ok_or(Error::Overflow)?;
+            }
+        }
+        Ok((filled_shares, buy_token_amount, fee))
+    }
+
+    // what setle_epoch would do for `token` if called now, deposit requests being only
+    // taken in the offer's buy token
+    pub fn preview_settlement(e: Env, token: Address) -> Result<SettlementPreview, Error> {
+        let epoch_id = Vault::get_epoch_id(e.clone())?;
+        quote_settlement(&e, &load_market(&e, &token)?, epoch_id)
+    }
+
+    // entry and exit fee charged on the given amount of buy token
+    pub fn preview_fees(e: Env, buy_token_amount: i128) -> Result<(i128, i128), Error> {
+        let fees = read_fees(&e)?;
+        Ok((
+            fee_amount(&e, buy_token_amount, fees.entry_fee_bps)?,
+            fee_amount(&e, buy_token_amount, fees.exit_fee_bps)?,
+        ))
+    }
+
+    pub fn get_limits(e: Env) -> Result<Limits, Error> {
+        Ok(read_limits(&e))
+    }
+
+    pub fn get_user_deposited(e: Env, user: Address) -> Result<i128, Error> {
+        Ok(read_user_deposited(&e, &user))
+    }
+
+    pub fn get_user_redeemed(e: Env, user: Address) -> Result<i128, Error> {
+        Ok(read_user_redeemed(&e, &user))
+    }
+
+    pub fn get_total_assets(e: Env) -> Result<i128, Error> {
+        Ok(read_total_assets(&e))
+    }
+
+    // rounding dust in `token` the seller can sweep
+    pub fn get_dust(e: Env, token: Address) -> Result<i128, Error> {
+        load_market(&e, &token)?;
+        Ok(read_dust(&e, &token))
+    }
+
+    // tokens of `token` the vault holds for investors, out of reach of `claim_leftover`
+    pub fn get_reserved(e: Env, token: Address) -> Result<i128, Error> {
+        Ok(read_reserved(&e, &token))
+    }
+
+    pub fn get_compliance(e: Env) -> Result<ComplianceConfig, Error> {
+        Ok(read_compliance(&e))
+    }
+
+    // expiry of the account's allowlist entry, 0 if it does not expire
+    pub fn get_allowed(e: Env, account: Address) -> Result<Option<u64>, Error> {
+        Ok(read_allowed(&e, &account))
+    }
+
+    pub fn is_denied(e: Env, account: Address) -> Result<bool, Error> {
+        Ok(is_denied(&e, &account))
+    }
+
+    // number of entries in the index of users that requested to redeem in `token` in the epoch
+    pub fn get_epoch_requester_count(e: Env, token: Address, epoch_id: u32) -> Result<u32, Error> {
+        load_market(&e, &token)?;
+        Ok(read_epoch_requester_count(&e, &token, epoch_id))
+    }
+
+    // up to `limit` users that requested to redeem in `token` in the epoch, from position `from`
+    // of the index
+    pub fn get_epoch_requesters(
+        e: Env,
+        token: Address,
+        epoch_id: u32,
+        from: u32,
+        limit: u32,
+    ) -> Result<Vec<Address>, Error> {
+        load_market(&e, &token)?;
+        Ok(read_epoch_requesters(&e, &token, epoch_id, from, limit))
+    }
+
+    // expiry of the operator's approval for the scope, 0 if it does not expire
+    pub fn get_operator(
+        e: Env,
+        owner: Address,
+        operator: Address,
+        scope: OperatorScope,
+    ) -> Result<Option<u64>, Error> {
+        Ok(read_operator(&e, &owner, &operator, scope))
+    }
+
+    pub fn is_receiver(e: Env, owner: Address, receiver: Address) -> Result<bool, Error> {
+        Ok(is_receiver(&e, &owner, &receiver))
+    }
+
+    pub fn get_oracle(e: Env) -> Result<Option<OracleConfig>, Error> {
+        Ok(read_oracle(&e))
+    }
+
+    pub fn get_buy_token_oracle(e: Env, token: Address) -> Result<Option<OracleConfig>, Error> {
+        read_buy_token(&e, &token).ok_or(Error::TokenNotAccepted)?;
+        Ok(read_token_oracle(&e, &token))
+    }
+
+    // price in `token` the next deposit or settlement would use
+    pub fn get_price(e: Env, token: Address) -> Result<Price, Error> {
+        current_price(&e, &load_market(&e, &token)?)
+    }
+
+    pub fn get_pending_seller(e: Env) -> Result<Option<PendingTransfer>, Error> {
+        Ok(read_pending(&e, &DataKey::PendingSeller))
+    }
+
+    pub fn get_pending_treasury(e: Env) -> Result<Option<PendingTransfer>, Error> {
+        Ok(read_pending(&e, &DataKey::PendingTreasury))
+    }
+
+    pub fn get_schema_version(e: Env) -> Result<u32, Error> {
+        Ok(read_schema_version(&e))
+    }
+
+    pub fn get_request(
+        e: Env,
+        sender: Address,
+        token: Address,
+        epoch_id: u32,
+    ) -> Result<RedeemRequest, Error> {
+        load_market(&e, &token)?;
+        Ok(read_redeem_request(&e, sender, &token, epoch_id))
+    }
+
+    // pending redeem requests of the sender paid in `token` from `from_epoch` to `to_epoch`,
+    // both included
+    pub fn get_requests(
+        e: Env,
+        sender: Address,
+        token: Address,
+        from_epoch: u32,
+        to_epoch: u32,
+    ) -> Result<Vec<RedeemRequest>, Error> {
+        load_market(&e, &token)?;
+        let mut requests = Vec::new(&e);
+        for epoch_id in read_request_epochs(&e, sender.clone(), &token).iter() {
+            if epoch_id > to_epoch {
+                break;
+            }
+            if epoch_id >= from_epoch {
+                requests.push_back(read_redeem_request(&e, sender.clone(), &token, epoch_id));
+            }
+        }
+        Ok(requests)
+    }
+
+    // shares issued by the vault and not burned yet, zero when it sells a separate token
+    pub fn total_supply(e: Env) -> Result<i128, Error> {
+        Ok(read_total_supply(&e))
+    }
+
+    pub fn get_epoch_id(e: Env) -> Result<u32, Error> {
+        e.storage()
+            .instance()
+            .get::<_, u32>(&DataKey::EpochId)
+            .ok_or(Error::OfferNotCreated)
+    }
+
+    // shares requested to redeem for `token` in the current epoch
+    pub fn get_total_redeem(e: Env, token: Address) -> Result<i128, Error> {
+        Vault::get_epoch_id(e.clone())?;
+        load_market(&e, &token)?;
+        Ok(read_total_redeem(&e, &token))
+    }
+
+    // shares requested for `token` after the cutoff, redeemed in the next epoch
+    pub fn get_next_total_redeem(e: Env, token: Address) -> Result<i128, Error> {
+        load_market(&e, &token)?;
+        Ok(e.storage()
+            .instance()
+            .get(&total_redeem_key(&e, &token, true))
+            .unwrap_or(0))
+    }
+
+    pub fn get_deposit_request(e: Env, sender: Address) -> Result<DepositRequest, Error> {
+        Ok(read_deposit_request(&e, sender))
+    }
+
+    pub fn get_total_deposit(e: Env) -> Result<i128, Error> {
+        Ok(e.storage()
+            .instance()
+            .get(&DataKey::TotalDeposit)
+            .unwrap_or(0))
+    }
+
+    // buy tokens deposited after the cutoff, settled in the next epoch
+    pub fn get_next_total_deposit(e: Env) -> Result<i128, Error> {
+        Ok(e.storage()
+            .instance()
+            .get(&DataKey::NextTotalDeposit)
+            .unwrap_or(0))
+    }
+
+    pub fn get_deposit_rate(e: Env, epoch_id: u32) -> Result<Price, Error> {
+        read_epoch_deposit(&e, epoch_id)
+            .map(|deposit| deposit.rate)
+            .ok_or(Error::EpochNotSetled)
+    }
+
+    // deposit rate and unclaimed buy tokens and shares of a settled epoch
+    pub fn get_epoch_deposit(e: Env, epoch_id: u32) -> Result<Option<EpochDeposit>, Error> {
+        Ok(read_epoch_deposit(&e, epoch_id))
+    }
+
+    // fill ratio and unclaimed shares of a settled epoch's redemptions in `token`
+    pub fn get_epoch_fill(
+        e: Env,
+        token: Address,
+        epoch_id: u32,
+    ) -> Result<Option<EpochFill>, Error> {
+        load_market(&e, &token)?;
+        Ok(read_epoch_fill(&e, &token, epoch_id))
+    }
+
+    // record of the epoch's redemptions in `token`, kept from its settlement on
+    pub fn get_epoch(e: Env, token: Address, epoch_id: u32) -> Result<Option<EpochInfo>, Error> {
+        load_market(&e, &token)?;
+        Ok(read_epoch_info(&e, &token, epoch_id))
+    }
+
+    // records of the epochs from `from_epoch` to `to_epoch` included, skipping epochs without one
+    pub fn get_epochs(
+        e: Env,
+        token: Address,
+        from_epoch: u32,
+        to_epoch: u32,
+    ) -> Result<Vec<EpochInfo>, Error> {
+        load_market(&e, &token)?;
+        // only settled epochs have a record
+        let last_settled = Vault::get_epoch_id(e.clone())?.saturating_sub(1);
+        let mut epochs = Vec::new(&e);
+        for epoch_id in from_epoch..=to_epoch.min(last_settled) {
+            if let Some(info) = read_epoch_info(&e, &token, epoch_id) {
+                epochs.push_back(info);
+            }
+        }
+        Ok(epochs)
+    }
+
+    pub fn get_schedule(e: Env) -> Result<Option<EpochSchedule>, Error> {
+        Ok(read_schedule(&e))
+    }
+
+    pub fn get_epoch_window(e: Env, epoch_id: u32) -> Result<Option<EpochWindow>, Error> {
+        Ok(read_epoch_window(&e, epoch_id))
+    }
+
+    pub fn get_redeem_rate(e: Env, token: Address, epoch_id: u32) -> Result<Price, Error> {
+        load_market(&e, &token)?;
+        read_redeem_rate(&e, &token, epoch_id).ok_or(Error::EpochNotSetled)
+    }
+}
+
+// SEP-41 interface of the shares, used when the vault is its own sell token. The interface
+// cannot return errors, so they are raised instead.
+#[contractimpl]
+impl TokenInterface for Vault {
+    fn allowance(e: Env, from: Address, spender: Address) -> i128 {
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        read_allowance(&e, from, spender).amount
+    }
+
+    fn approve(e: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        raise(
+            &e,
+            approve_shares(&e, from, spender, amount, expiration_ledger),
+        )
+    }
+
+    fn balance(e: Env, id: Address) -> i128 {
+        e.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        read_balance(&e, id)
+    }
+
+    fn transfer(e: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        raise(&e, transfer_shares(&e, from, to, amount))
+    }
+
+    fn transfer_from(e: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+        raise(&e, spend_allowance_checked(&e, &spender, &from, amount));
+        raise(&e, transfer_shares(&e, from, to, amount))
+    }
+
+    fn burn(e: Env, from: Address, amount: i128) {
+        from.require_auth();
+        raise(&e, burn_shares(&e, from, amount))
+    }
+
+    fn burn_from(e: Env, spender: Address, from: Address, amount: i128) {
+        spender.require_auth();
+        raise(&e, spend_allowance_checked(&e, &spender, &from, amount));
+        raise(&e, burn_shares(&e, from, amount))
+    }
+
+    fn decimals(e: Env) -> u32 {
+        raise(&e, load_offer(&e)).sell_decimals
+    }
+
+    fn name(e: Env) -> String {
+        TokenUtils::new(&e).metadata().get_metadata().name
+    }
+
+    fn symbol(e: Env) -> String {
+        TokenUtils::new(&e).metadata().get_metadata().symbol
+    }
+}
+
+fn raise<T>(e: &Env, result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|error| panic_with_error!(e, error))
+}
+
+fn approve_shares(
+    e: &Env,
+    from: Address,
+    spender: Address,
+    amount: i128,
+    expiration_ledger: u32,
+) -> Result<(), Error> {
+    from.require_auth();
+
+    check_nonnegative_amount(amount)?;
+
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+    write_allowance(e, from.clone(), spender.clone(), amount, expiration_ledger)?;
+    TokenUtils::new(e)
+        .events()
+        .approve(from, spender, amount, expiration_ledger);
+    Ok(())
+}
+
+fn spend_allowance_checked(
+    e: &Env,
+    spender: &Address,
+    from: &Address,
+    amount: i128,
+) -> Result<(), Error> {
+    check_nonnegative_amount(amount)?;
+    spend_allowance(e, from.clone(), spender.clone(), amount)
+}
+
+// move shares between investors, the sender's authorization is checked by the caller
+fn transfer_shares(e: &Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+    check_nonnegative_amount(amount)?;
+    require_not_denied(e, &from)?;
+    require_compliant(e, &to)?;
+
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+    spend_balance(e, from.clone(), amount)?;
+    receive_balance(e, to.clone(), amount)?;
+    TokenUtils::new(e).events().transfer(from, to, amount);
+    Ok(())
+}
+
+// burn shares of an investor, the investor's authorization is checked by the caller
+fn burn_shares(e: &Env, from: Address, amount: i128) -> Result<(), Error> {
+    check_nonnegative_amount(amount)?;
+    require_not_denied(e, &from)?;
+
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+    burn_balance(e, from.clone(), amount)?;
+    TokenUtils::new(e).events().burn(from, amount);
+    Ok(())
+}
+
+// requests for the current epoch add to the token's total, late requests to the next epoch's
+// total
+fn total_redeem_key(e: &Env, token: &Address, next: bool) -> DataKey {
+    match (is_base_token(e, token), next) {
+        (true, false) => DataKey::TotalRedeem,
+        (true, true) => DataKey::NextTotalRedeem,
+        (false, false) => DataKey::TokenTotalRedeem(token.clone()),
+        (false, true) => DataKey::TokenNextTotalRedeem(token.clone()),
+    }
+}
+
+fn read_total_redeem(e: &Env, token: &Address) -> i128 {
+    e.storage()
+        .instance()
+        .get(&total_redeem_key(e, token, false))
+        .unwrap_or(0)
+}
+
+// start the token's total of the next epoch with the unfilled shares carried over and the
+// requests that came after the cutoff
+fn roll_total_redeem(e: &Env, token: &Address, unfilled_shares: i128) -> Result<(), Error> {
+    let next_key = total_redeem_key(e, token, true);
+    let next_total_redeem: i128 = e.storage().instance().get(&next_key).unwrap_or(0);
+    let new_total_redeem = next_total_redeem
+        .checked_add(unfilled_shares)
+        .ok_or(Error::Overflow)?;
+    e.storage()
+        .instance()
+        .set(&total_redeem_key(e, token, false), &new_total_redeem);
+    e.storage().instance().remove(&next_key);
+    Ok(())
+}
+
+// shares requested over all buy tokens in the epoch a request made now belongs to
+fn requested_shares(e: &Env, epoch_id: u32, request_epoch_id: u32) -> Result<i128, Error> {
+    let mut total: i128 = 0;
+    for token in accepted_tokens(e, &load_offer(e)?).iter() {
+        let total_key = total_redeem_key(e, &token, request_epoch_id > epoch_id);
+        let requested: i128 = e.storage().instance().get(&total_key).unwrap_or(0);
+        total = total.checked_add(requested).ok_or(Error::Overflow)?;
+    }
+    Ok(total)
+}
+
+// value of `amount` of the market's buy token in the offer's buy token at the current prices,
+// the unit the limits are set in
+fn base_value(e: &Env, offer: &Offer, market: &Offer, amount: i128) -> Result<i128, Error> {
+    if market.buy_token == offer.buy_token {
+        return Ok(amount);
+    }
+    let shares = assets_to_shares(e, market, &current_price(e, market)?, amount)?;
+    shares_to_assets(e, offer, &current_price(e, offer)?, shares)
+}
+
+// return the escrowed shares of a request that is not settled yet
+fn cancel_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: Address,
+    request_epoch_id: u32,
+) -> Result<(), Error> {
+    let market = load_market(e, &token)?;
+    let redeem_request = read_redeem_request(e, sender.clone(), &token, request_epoch_id);
+    if redeem_request.shares_amount <= 0 {
+        return Err(Error::NoRedeemRequest);
+    }
+    // shares of settled requests were already sent to the seller
+    let epoch_id = Vault::get_epoch_id(e.clone())?;
+    if request_epoch_id < epoch_id {
+        return Err(Error::InvalidEpochId);
+    }
+    let total_key = total_redeem_key(e, &token, request_epoch_id > epoch_id);
+    let total_redeem: i128 = e.storage().instance().get(&total_key).unwrap_or(0);
+    let new_total_redeem = total_redeem
+        .checked_sub(redeem_request.shares_amount)
+        .ok_or(Error::Overflow)?;
+    e.storage().instance().set(&total_key, &new_total_redeem);
+    remove_redeem(e, &sender, redeem_request.shares_amount)?;
+
+    release_shares(e, &market, &sender, redeem_request.shares_amount)?;
+
+    delete_redeem_request(e, sender.clone(), &token, request_epoch_id)?;
+    e.events().publish(
+        (Symbol::new(e, "vault_cancel_request"), sender, token),
+        (
+            request_epoch_id,
+            redeem_request.shares_amount,
+            new_total_redeem,
+        ),
+    );
+    Ok(())
+}
+
+fn total_deposit_key(epoch_id: u32, request_epoch_id: u32) -> DataKey {
+    if request_epoch_id > epoch_id {
+        DataKey::NextTotalDeposit
+    } else {
+        DataKey::TotalDeposit
+    }
+}
+
+// convert the epoch's deposits into shares at the settlement price, the buy tokens go to the
+// treasury after the entry fee and the shares are taken from the vault's balance on claim
+fn setle_deposits(e: &Env, offer: &Offer, quote: &SettlementPreview) -> Result<(), Error> {
+    let epoch_id = quote.epoch_id;
+    let total_deposit = quote.total_deposit;
+    let fee = quote.deposit_fee;
+    let net_amount = total_deposit.checked_sub(fee).ok_or(Error::Overflow)?;
+    let shares = quote.deposit_shares;
+    let price = quote.price.clone();
+    write_epoch_deposit(
+        e,
+        epoch_id,
+        &EpochDeposit {
+            rate: price.clone(),
+            assets_left: total_deposit,
+            shares_left: shares,
+        },
+    );
+
+    let next_total_deposit: i128 = e
+        .storage()
+        .instance()
+        .get(&DataKey::NextTotalDeposit)
+        .unwrap_or(0);
+    e.storage()
+        .instance()
+        .set(&DataKey::TotalDeposit, &next_total_deposit);
+    e.storage().instance().remove(&DataKey::NextTotalDeposit);
+
+    // the deposits leave the vault, the shares it hands out at claim stay until then
+    unreserve(e, &offer.buy_token, total_deposit);
+    if !is_share_token(e, offer) {
+        reserve(e, &offer.sell_token, shares)?;
+    }
+
+    let fees = read_fees(e)?;
+    let buy_token_client = token::Client::new(e, &offer.buy_token);
+    let contract_address = e.current_contract_address();
+    if net_amount > 0 {
+        buy_token_client.transfer(&contract_address, &offer.treasury, &net_amount);
+    }
+    if fee > 0 {
+        buy_token_client.transfer(&contract_address, &fees.recipient, &fee);
+    }
+
+    e.events().publish(
+        (Symbol::new(e, "vault_setle_deposits"), epoch_id),
+        (total_deposit, shares, fee, price),
+    );
+    Ok(())
+}
+
+// record the epoch's redemptions in the market's buy token at the quoted price, burn the filled
+// shares and take the funded buy tokens from the market's treasury. The unfilled shares carry
+// over to the next epoch.
+fn setle_redemptions(e: &Env, market: &Offer, quote: &SettlementPreview) -> Result<(), Error> {
+    let token = &market.buy_token;
+    write_redeem_rate(e, token, quote.epoch_id, &quote.price);
+    write_epoch_fill(
+        e,
+        token,
+        quote.epoch_id,
+        &EpochFill {
+            fill_ratio: quote.fill_ratio,
+            shares_left: quote.total_redeem,
+            filled_left: quote.filled_shares,
+            assets_left: quote.funded_asset,
+        },
+    );
+    let unfilled_shares = quote
+        .total_redeem
+        .checked_sub(quote.filled_shares)
+        .ok_or(Error::Overflow)?;
+    roll_total_redeem(e, token, unfilled_shares)?;
+    write_epoch_info(
+        e,
+        token,
+        &EpochInfo {
+            epoch_id: quote.epoch_id,
+            total_redeem: quote.total_redeem,
+            redeemed_shares: quote.filled_shares,
+            funded_asset: quote.funded_asset,
+            claimed_asset: 0,
+            price: quote.price.clone(),
+            settled_at: e.ledger().timestamp(),
+        },
+    );
+
+    retire_shares(e, market, quote.filled_shares)?;
+    // the treasury only signs for epochs it actually funds
+    if quote.funded_asset > 0 {
+        token::Client::new(e, token).transfer(
+            &market.treasury,
+            &e.current_contract_address(),
+            &quote.funded_asset,
+        );
+    }
+    reserve(e, token, quote.funded_asset)
+}
+
+// hand out the shares of a settled deposit request
+fn claim_deposit_request(e: &Env, sender: Address) -> Result<(), Error> {
+    let deposit_request = read_deposit_request(e, sender.clone());
+    if deposit_request.assets_amount <= 0 {
+        return Err(Error::NoDepositRequest);
+    }
+    let shares = take_deposit_shares(e, deposit_request.epoch_id, deposit_request.assets_amount)?;
+
+    let offer = load_offer(e)?;
+    if !is_share_token(e, &offer) {
+        unreserve(e, &offer.sell_token, shares);
+    }
+    issue_shares(e, &offer, &sender, shares)?;
+
+    delete_deposit_request(e, sender.clone());
+    e.events().publish(
+        (Symbol::new(e, "vault_claim_deposit"), sender),
+        (deposit_request.assets_amount, shares),
+    );
+    Ok(())
+}
+
+// split a deposit into the shares it buys at the current price and the entry fee
+fn quote_deposit(e: &Env, offer: &Offer, buy_token_amount: i128) -> Result<(i128, i128), Error> {
+    let fee = fee_amount(e, buy_token_amount, read_fees(e)?.entry_fee_bps)?;
+    let net_amount = buy_token_amount.checked_sub(fee).ok_or(Error::Overflow)?;
+    let price = current_price(e, offer)?;
+    let sell_token_amount = assets_to_shares(e, offer, &price, net_amount)?;
+    Ok((sell_token_amount, fee))
+}
+
+// settle the epoch's deposits and redemptions in the market's buy token at the current price
+// on paper
+fn quote_settlement(e: &Env, offer: &Offer, epoch_id: u32) -> Result<SettlementPreview, Error> {
+    let price = current_price(e, offer)?;
+    let fees = read_fees(e)?;
+    // only the offer's buy token takes deposit requests
+    let total_deposit: i128 = if is_base_token(e, &offer.buy_token) {
+        e.storage()
+            .instance()
+            .get(&DataKey::TotalDeposit)
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    let deposit_fee = fee_amount(e, total_deposit, fees.entry_fee_bps)?;
+    let net_deposit = total_deposit
+        .checked_sub(deposit_fee)
+        .ok_or(Error::Overflow)?;
+    let deposit_shares = assets_to_shares(e, offer, &price, net_deposit)?;
+
+    let total_redeem = read_total_redeem(e, &offer.buy_token);
+    let total_asset = shares_to_assets(e, offer, &price, total_redeem)?;
+
+    // the treasury funds what it can once the deposits reached it, the unfilled shares carry
+    // over to the next epoch
+    let buy_token_client = token::Client::new(e, &offer.buy_token);
+    let mut available = buy_token_client.balance(&offer.treasury);
+    if offer.treasury != e.current_contract_address() {
+        available = available.checked_add(net_deposit).ok_or(Error::Overflow)?;
+        if fees.recipient == offer.treasury {
+            available = available.checked_add(deposit_fee).ok_or(Error::Overflow)?;
+        }
+    }
+    let (filled_shares, funded_asset) = if available >= total_asset {
+        (total_redeem, total_asset)
+    } else {
+        let filled_shares = assets_to_shares(e, offer, &price, available)?;
+        let funded_asset = shares_to_assets(e, offer, &price, filled_shares)?;
+        (filled_shares, funded_asset)
+    };
+    let fill_ratio = if total_redeem > 0 {
+        mul_div_floor(e, filled_shares, FULL_FILL as i128, total_redeem)? as u32
+    } else {
+        FULL_FILL
+    };
+
+    Ok(SettlementPreview {
+        epoch_id,
+        price,
+        total_deposit,
+        deposit_shares,
+        deposit_fee,
+        total_redeem,
+        filled_shares,
+        funded_asset,
+        fill_ratio,
+    })
+}
+
+// walk the settled epochs from the request's epoch, taking the request's part of their filled
+// shares and funded buy tokens as left in `fills`, and return the filled shares, the unfilled
+// shares and the buy tokens the filled shares are worth before the exit fee
+fn fill_redeem_request(
+    e: &Env,
+    offer: &Offer,
+    fills: &mut Map<u32, EpochFill>,
+    request_epoch_id: u32,
+    shares_amount: i128,
+    epoch_id: u32,
+) -> Result<(i128, i128, i128), Error> {
+    // unfilled shares of a partially settled epoch were carried over to the following one
+    let mut shares_amount = shares_amount;
+    let mut filled_shares: i128 = 0;
+    let mut gross_amount: i128 = 0;
+    let mut settled_epoch_id = request_epoch_id;
+    while settled_epoch_id < epoch_id && shares_amount > 0 {
+        let redeem_rate =
+            read_redeem_rate(e, &offer.buy_token, settled_epoch_id).ok_or(Error::EpochNotSetled)?;
+        let fill = fills
+            .get(settled_epoch_id)
+            .or_else(|| read_epoch_fill(e, &offer.buy_token, settled_epoch_id));
+        let (filled, assets) = match fill {
+            Some(mut fill) => {
+                let filled = fill_shares(e, &mut fill, shares_amount)?;
+                let assets = shares_to_assets(e, offer, &redeem_rate, filled)?;
+                fill.assets_left = fill
+                    .assets_left
+                    .checked_sub(assets)
+                    .ok_or(Error::Overflow)?;
+                fills.set(settled_epoch_id, fill);
+                (filled, assets)
+            }
+            // settled before partial settlements existed, so fully filled
+            None => (
+                shares_amount,
+                shares_to_assets(e, offer, &redeem_rate, shares_amount)?,
+            ),
+        };
+        gross_amount = gross_amount.checked_add(assets).ok_or(Error::Overflow)?;
+        filled_shares = filled_shares.checked_add(filled).ok_or(Error::Overflow)?;
+        shares_amount = shares_amount.checked_sub(filled).ok_or(Error::Overflow)?;
+        settled_epoch_id += 1;
+    }
+    Ok((filled_shares, shares_amount, gross_amount))
+}
+
+// cancel every redeem request of the sender not settled yet, in every accepted token
+fn cancel_redeem_requests(e: &Env, sender: &Address) -> Result<(), Error> {
+    require_not_denied(e, sender)?;
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    let epoch_id = Vault::get_epoch_id(e.clone())?;
+    let mut cancelled = false;
+    for token in accepted_tokens(e, &load_offer(e)?).iter() {
+        for request_epoch_id in read_request_epochs(e, sender.clone(), &token).iter() {
+            if request_epoch_id >= epoch_id {
+                cancel_redeem_request(e, sender.clone(), token.clone(), request_epoch_id)?;
+                cancelled = true;
+            }
+        }
+    }
+    if !cancelled {
+        return Err(Error::NoRedeemRequest);
+    }
+    Ok(())
+}
+
+// claim every settled redeem request of the sender paid in `token`, paying the receiver
+fn claim_redeem_requests(
+    e: &Env,
+    sender: &Address,
+    token: &Address,
+    receiver: &Address,
+) -> Result<(), Error> {
+    require_not_paused(e, Operation::Claim)?;
+    require_not_denied(e, sender)?;
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    let epoch_id = Vault::get_epoch_id(e.clone())?;
+    let mut claimed = false;
+    load_market(e, token)?;
+    for request_epoch_id in read_request_epochs(e, sender.clone(), token).iter() {
+        if request_epoch_id < epoch_id {
+            claim_redeem_request(e, sender.clone(), token.clone(), request_epoch_id, receiver)?;
+            claimed = true;
+        }
+    }
+    if !claimed {
+        return Err(Error::NoRedeemRequest);
+    }
+    Ok(())
+}
+
+// record that the operator called `action` for the owner, paying `receiver`
+fn publish_operator_action(
+    e: &Env,
+    operator: &Address,
+    owner: &Address,
+    action: &str,
+    receiver: &Address,
+) {
+    e.events().publish(
+        (
+            Symbol::new(e, "vault_operator_action"),
+            operator.clone(),
+            owner.clone(),
+        ),
+        (Symbol::new(e, action), receiver.clone()),
+    );
+}
+
+// add shares to the sender's redeem request, escrowed through the sender's allowance when an
+// operator requests on its behalf. Authorization is checked by the caller.
+fn submit_redeem_request(
+    e: &Env,
+    sender: &Address,
+    token: &Address,
+    amount: i128,
+    by_operator: bool,
+) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::NegativeRedeemAmount);
+    }
+    require_not_paused(e, Operation::RedeemRequest)?;
+    require_compliant(e, sender)?;
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    let market = load_open_market(e, token)?;
+    let epoch_id = Vault::get_epoch_id(e.clone())?;
+    let request_epoch_id = request_epoch_id(e, epoch_id)?;
+    let prev_redeem_request = read_redeem_request(e, sender.clone(), token, request_epoch_id);
+
+    let total_key = total_redeem_key(e, token, request_epoch_id > epoch_id);
+    let total_redeem: i128 = e.storage().instance().get(&total_key).unwrap_or(0);
+    let new_total_redeem = total_redeem.checked_add(amount).ok_or(Error::Overflow)?;
+    e.storage().instance().set(&total_key, &new_total_redeem);
+    let epoch_total = requested_shares(e, epoch_id, request_epoch_id)?;
+    add_redeem(e, sender, amount, epoch_total)?;
+
+    let new_redeem_amount = prev_redeem_request
+        .shares_amount
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    write_redeem_request(
+        e,
+        sender.clone(),
+        token,
+        new_redeem_amount,
+        request_epoch_id,
+    )?;
+
+    if by_operator {
+        escrow_approved_shares(e, &market, sender, amount)?;
+    } else {
+        escrow_shares(e, &market, sender, amount)?;
+    }
+
+    e.events().publish(
+        (
+            Symbol::new(e, "vault_redeem_request"),
+            sender.clone(),
+            token.clone(),
+        ),
+        (
+            amount,
+            new_redeem_amount,
+            new_total_redeem,
+            request_epoch_id,
+        ),
+    );
+    Ok(())
+}
+
+// pay out a settled redeem request to the receiver, the sender's authorization is checked by
+// the caller
+fn claim_redeem_request(
+    e: &Env,
+    sender: Address,
+    token: Address,
+    request_epoch_id: u32,
+    receiver: &Address,
+) -> Result<(), Error> {
+    let market = load_market(e, &token)?;
+    let redeem_request = read_redeem_request(e, sender.clone(), &token, request_epoch_id);
+    let epoch_id: u32 = Vault::get_epoch_id(e.clone())?;
+    if epoch_id <= request_epoch_id {
+        return Err(Error::EpochNotSetled);
+    }
+
+    if redeem_request.shares_amount <= 0 {
+        return Err(Error::NoRedeemRequest);
+    }
+    let mut fills = Map::new(e);
+    let (filled_shares, shares_amount, gross_amount) = fill_redeem_request(
+        e,
+        &market,
+        &mut fills,
+        request_epoch_id,
+        redeem_request.shares_amount,
+        epoch_id,
+    )?;
+    // payouts are rounded down, what the last claim of an epoch leaves is dust
+    for (settled_epoch_id, mut fill) in fills.iter() {
+        if let Some(settled_fill) = read_epoch_fill(e, &token, settled_epoch_id) {
+            let claimed = settled_fill
+                .assets_left
+                .checked_sub(fill.assets_left)
+                .ok_or(Error::Overflow)?;
+            add_epoch_claim(e, &token, settled_epoch_id, claimed)?;
+        }
+        if fill.shares_left == 0 && fill.assets_left > 0 {
+            add_dust(e, &token, fill.assets_left)?;
+            fill.assets_left = 0;
+        }
+        write_epoch_fill(e, &token, settled_epoch_id, &fill);
+    }
+    let fees = read_fees(e)?;
+    let fee = fee_amount(e, gross_amount, fees.exit_fee_bps)?;
+    let buy_token_amount = gross_amount.checked_sub(fee).ok_or(Error::Overflow)?;
+
+    unreserve(e, &token, gross_amount);
+    pay_out(e, &token, receiver, buy_token_amount)?;
+    if fee > 0 {
+        pay_out(e, &token, &fees.recipient, fee)?;
+    }
+
+    // what is still unfilled waits in the current epoch
+    delete_redeem_request(e, sender.clone(), &token, request_epoch_id)?;
+    if shares_amount > 0 {
+        let current_request = read_redeem_request(e, sender.clone(), &token, epoch_id);
+        let new_redeem_amount = current_request
+            .shares_amount
+            .checked_add(shares_amount)
+            .ok_or(Error::Overflow)?;
+        write_redeem_request(e, sender.clone(), &token, new_redeem_amount, epoch_id)?;
+    }
+
+    e.events().publish(
+        (Symbol::new(e, "vault_claim_request"), sender, token),
+        (request_epoch_id, filled_shares, buy_token_amount, fee),
+    );
+    Ok(())
+}
+
+fn initialize_vault(
+    e: &Env,
+    admin: Address,
+    seller: Address,
+    treasury: Address,
+    sell_token: Address,
+    buy_token: Address,
+    initial_price: Price,
+) -> Result<(), Error> {
+    check_price(&initial_price)?;
+    let (sell_decimals, buy_decimals) = read_token_decimals(e, &sell_token, &buy_token);
+
+    write_offer(
+        e,
+        &Offer {
+            seller: seller.clone(),
+            treasury: treasury.clone(),
+            sell_token: sell_token.clone(),
+            buy_token: buy_token.clone(),
+            price: initial_price.clone(),
+            sell_decimals,
+            buy_decimals,
+        },
+    );
+    e.storage().instance().set(&DataKey::TotalRedeem, &0_i128);
+    e.storage().instance().set(&DataKey::EpochId, &1_u32);
+
+    write_initial_roles(e, &admin, &seller, &treasury);
+    write_schema_version(e, SCHEMA_VERSION);
+
+    e.events().publish(
+        (
+            Symbol::new(e, "vault_initialized"),
+            seller,
+            treasury,
+            sell_token,
+            buy_token,
+        ),
+        initial_price,
+    );
+    Ok(())
 }
@@ -0,0 +1,8 @@
+const { invokeContract, loadFixtures } = require("./utils");
+
+const { distributorKeys, vault } = loadFixtures();
+
+// deploy-testnet.sh creates the offer in the constructor, read it back
+const call = vault.call("get_offer");
+
+invokeContract(distributorKeys, call).then(console.log);
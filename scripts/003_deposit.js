@@ -2,11 +2,14 @@ const StellarSdk = require("@stellar/stellar-sdk");
 
 const { invokeContract, loadFixtures } = require("./utils");
 
-const { vault, aliceKeys } = loadFixtures();
+const { vault, aliceKeys, usdc } = loadFixtures();
 
 const call = vault.call(
   "deposit",
   StellarSdk.nativeToScVal(aliceKeys.publicKey(), { type: "address" }),
+  StellarSdk.nativeToScVal(usdc.contractId(StellarSdk.Networks.TESTNET), {
+    type: "address",
+  }),
   new StellarSdk.ScInt(100000000).toI128(), // 10 USDyc
   new StellarSdk.ScInt(100000000).toI128()
 );
@@ -2,11 +2,14 @@ const StellarSdk = require("@stellar/stellar-sdk");
 
 const { invokeContract, loadFixtures } = require("./utils");
 
-const { vault, aliceKeys } = loadFixtures(); // Load the fixtures
+const { vault, aliceKeys, usdc } = loadFixtures(); // Load the fixtures
 
 const call = vault.call(
   "redeem_request",
   StellarSdk.nativeToScVal(aliceKeys.publicKey(), { type: "address" }),
+  StellarSdk.nativeToScVal(usdc.contractId(StellarSdk.Networks.TESTNET), {
+    type: "address",
+  }),
   new StellarSdk.ScInt(10000000).toI128() // 1 USDyc
 );
 
@@ -1,13 +1,14 @@
 const StellarSdk = require("@stellar/stellar-sdk");
 
-const { invokeContract, loadFixtures } = require("./utils");
+const { invokeContract, loadFixtures, priceToScVal } = require("./utils");
 
 const { vault, distributorKeys } = loadFixtures(); // Load the fixtures
 
+// the seller is the vault's first price updater
 const call = vault.call(
   "updt_price",
-  StellarSdk.xdr.ScVal.scvU32(100),
-  StellarSdk.xdr.ScVal.scvU32(200)
+  StellarSdk.nativeToScVal(distributorKeys.publicKey(), { type: "address" }),
+  priceToScVal(2000000, 6)
 ); // 1 USDyc = 2 USDC
 
 invokeContract(distributorKeys, call); // Invoke the contract
@@ -4,12 +4,14 @@ const { invokeContract, loadFixtures } = require("./utils");
 
 const { vault, distributorKeys, usdyc } = loadFixtures();
 
+// the seller is the vault's first sweeper
 const call = vault.call(
   "claim_leftover",
+  StellarSdk.nativeToScVal(distributorKeys.publicKey(), { type: "address" }),
   StellarSdk.nativeToScVal(usdyc.contractId(StellarSdk.Networks.TESTNET), {
     type: "address",
   }),
-  StellarSdk.xdr.ScVal.scvI128(10000000000) // 1000 USDyc
+  new StellarSdk.ScInt(10000000000).toI128() // 1000 USDyc
 );
 
 invokeContract(distributorKeys, call);
@@ -1,8 +1,19 @@
 #!bin/bash
+# the constructor creates the offer: the distributor, also the admin, sells testUSDyc for USDC
+# at 1 USDC. distributor, treasury and issuer are stellar keys identities.
 stellar contract build
 
+ISSUER=$(stellar keys address issuer)
+
 stellar contract deploy \
 --source-account testnet-deployer \
 --wasm ./target/wasm32-unknown-unknown/release/untangled-vault.wasm \
---network testnet \ 
---alias vault
\ No newline at end of file
+--network testnet \
+--alias vault \
+-- \
+--admin distributor \
+--seller distributor \
+--treasury treasury \
+--sell_token "$(stellar contract id asset --asset testUSDyc:$ISSUER --network testnet)" \
+--buy_token "$(stellar contract id asset --asset USDC:$ISSUER --network testnet)" \
+--initial_price '{"mantissa":"1000000","decimals":6}'
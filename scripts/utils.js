@@ -72,6 +72,20 @@ function createAssetContract(account, asset) {
   });
 }
 
+// the vault's `Price`, a mantissa scaled by 10^decimals, as a map with sorted keys
+function priceToScVal(mantissa, decimals) {
+  return StellarSdk.xdr.ScVal.scvMap([
+    new StellarSdk.xdr.ScMapEntry({
+      key: StellarSdk.xdr.ScVal.scvSymbol("decimals"),
+      val: StellarSdk.xdr.ScVal.scvU32(decimals),
+    }),
+    new StellarSdk.xdr.ScMapEntry({
+      key: StellarSdk.xdr.ScVal.scvSymbol("mantissa"),
+      val: new StellarSdk.ScInt(mantissa).toI128(),
+    }),
+  ]);
+}
+
 function loadFixtures() {
   const issuerKeys = StellarSdk.Keypair.fromSecret(process.env.ISSUER_KEYS);
   const distributorKeys = StellarSdk.Keypair.fromSecret(
@@ -104,5 +118,6 @@ module.exports = {
   invokeContract,
   invokeOperation,
   createAssetContract,
+  priceToScVal,
   loadFixtures,
 };